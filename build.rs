@@ -0,0 +1,6 @@
+fn main() {
+	println!("cargo:rerun-if-changed=build.rs");
+
+	#[cfg(feature = "luajit")]
+	luajit_src::Build::new().build().print_cargo_metadata();
+}
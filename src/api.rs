@@ -1,4 +1,11 @@
-use crate::{compiler::BytecodeCompiler, lua::LuaInt};
+use std::{path::PathBuf, sync::Arc};
+
+use crate::{
+	compiler::{BytecodeCompiler, CompilerBuilder},
+	lua::LuaInt,
+	pool::CompilerPool,
+	Diagnostic,
+};
 
 pub type Bytecode = Vec<u8>;
 
@@ -32,10 +39,186 @@ pub enum LuaError {
 	/// Unknown Lua error code
 	Unknown(LuaInt),
 
+	/// `lua_shared` could not be loaded.
+	LoadError(LoadError),
+
+	/// A string that has to be passed to Lua as a C string, such as a chunk name or file path, contained a NUL byte.
+	NulError(std::ffi::NulError),
+
+	/// An I/O error occurred while reading source code or writing bytecode.
+	IoError(Arc<std::io::Error>),
+
 	#[cfg(not(feature = "parking_lot"))]
 	/// The Mutex guarding the Lua state is poisoned by a panic in another thread.
 	PoisonError,
 }
+impl LuaError {
+	/// Parses the location, message and offending token out of a `SyntaxError`'s message.
+	///
+	/// Returns `None` for other errors, or if the message isn't in Lua's usual `chunk:line: message` format.
+	pub fn diagnostic(&self) -> Option<Diagnostic> {
+		match self {
+			LuaError::SyntaxError(Some(message)) => Diagnostic::parse(message),
+			_ => None,
+		}
+	}
+}
+impl std::fmt::Display for LuaError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			LuaError::MemoryAllocationError => write!(f, "out of memory"),
+			LuaError::SyntaxError(Some(message)) => write!(f, "{}", message),
+			LuaError::SyntaxError(None) => write!(f, "syntax error"),
+			LuaError::FileError(Some(message)) => write!(f, "{}", message),
+			LuaError::FileError(None) => write!(f, "failed to load file"),
+			LuaError::RuntimeError(Some(message)) => write!(f, "{}", message),
+			LuaError::RuntimeError(None) => write!(f, "runtime error"),
+			LuaError::ErrorHandlerError => write!(f, "error while running the error handler function"),
+			LuaError::Unknown(code) => write!(f, "unknown Lua error code {}", code),
+			LuaError::LoadError(error) => write!(f, "{}", error),
+			LuaError::NulError(error) => write!(f, "string contains a NUL byte at position {}", error.nul_position()),
+			LuaError::IoError(error) => write!(f, "{}", error),
+			#[cfg(not(feature = "parking_lot"))]
+			LuaError::PoisonError => write!(f, "the Lua state's mutex was poisoned by a panic in another thread"),
+		}
+	}
+}
+impl std::error::Error for LuaError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			LuaError::LoadError(error) => Some(error),
+			LuaError::NulError(error) => Some(error),
+			LuaError::IoError(error) => Some(&**error),
+			_ => None,
+		}
+	}
+}
+impl From<LoadError> for LuaError {
+	fn from(error: LoadError) -> Self {
+		LuaError::LoadError(error)
+	}
+}
+impl From<std::ffi::NulError> for LuaError {
+	fn from(error: std::ffi::NulError) -> Self {
+		LuaError::NulError(error)
+	}
+}
+impl From<std::io::Error> for LuaError {
+	fn from(error: std::io::Error) -> Self {
+		LuaError::IoError(Arc::new(error))
+	}
+}
+
+/// A library path that was tried while loading `lua_shared`, and why loading it failed.
+#[derive(Debug, Clone)]
+pub struct LoadAttempt {
+	pub path: PathBuf,
+	pub error: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum LoadError {
+	/// None of the candidate libraries could be loaded.
+	LibraryNotFound { tried: Vec<LoadAttempt> },
+
+	/// A library was found, but it was built for a different pointer width to this executable.
+	///
+	/// You're probably using the binaries from the wrong branch of Garry's Mod.
+	WrongBitness {
+		library: PathBuf,
+		bitness: usize,
+		tried: Vec<LoadAttempt>,
+	},
+
+	/// The library was loaded, but a symbol we need is missing from it.
+	///
+	/// [`LuaApi::load`](crate::lua::LuaApi::load) moves on to the next candidate when this happens, recording this error in its [`LoadAttempt`].
+	SymbolNotFound { library: PathBuf, symbol: String, error: String },
+}
+impl LoadError {
+	/// Returns every library path that was tried, and why it failed to load.
+	pub fn tried(&self) -> &[LoadAttempt] {
+		match self {
+			LoadError::LibraryNotFound { tried } | LoadError::WrongBitness { tried, .. } => tried,
+			LoadError::SymbolNotFound { .. } => &[],
+		}
+	}
+
+	/// Whether Garry's Mod's `lua_shared` was being loaded, rather than another [`Backend`](crate::backend::Backend)'s library.
+	fn is_lua_shared(&self) -> bool {
+		match self {
+			LoadError::LibraryNotFound { tried } | LoadError::WrongBitness { tried, .. } => tried.iter().any(|attempt| {
+				let name = attempt.path.file_name().and_then(|name| name.to_str());
+				matches!(name, Some(name) if crate::library::LIBRARY_NAMES.contains(&name))
+			}),
+			LoadError::SymbolNotFound { .. } => true,
+		}
+	}
+}
+impl std::fmt::Display for LoadError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			LoadError::LibraryNotFound { .. } if !self.is_lua_shared() => writeln!(f, "Failed to load LuaJIT!")?,
+
+			LoadError::LibraryNotFound { .. } => {
+				#[cfg(target_os = "windows")]
+				writeln!(f, "Failed to load lua_shared.dll, tier0.dll or vstdlib.dll!")?;
+
+				#[cfg(not(target_os = "windows"))]
+				writeln!(
+					f,
+					"Failed to load lua_shared_srv.so/lua_shared.so, libtier0_srv.so/libtier0.so or libvstdlib_srv.so/libvstdlib.so!"
+				)?;
+			}
+
+			LoadError::WrongBitness { library, bitness, .. } => writeln!(
+				f,
+				"{} is a {}-bit library, but this executable is {}-bit!",
+				library.display(),
+				bitness,
+				crate::library::TARGET_BITNESS
+			)?,
+
+			LoadError::SymbolNotFound { library, symbol, error } => {
+				return write!(f, "Failed to find symbol \"{}\" in {}: {}", symbol, library.display(), error);
+			}
+		}
+
+		for attempt in self.tried() {
+			writeln!(f, "Tried {}: {}", attempt.path.display(), attempt.error)?;
+		}
+
+		if !self.is_lua_shared() {
+			return write!(f, "The library must be in the system's library search path, or be given by its path.");
+		}
+
+		#[cfg(target_pointer_width = "32")]
+		writeln!(
+			f,
+			"Make sure you are using the 32-bit module binaries from the 32-bit branch of Garry's Mod."
+		)?;
+
+		#[cfg(target_pointer_width = "64")]
+		writeln!(
+			f,
+			"Make sure you are using the 64-bit module binaries from the 64-bit branch of Garry's Mod."
+		)?;
+
+		write!(
+			f,
+			"The binaries must be placed in the same directory as the executable, or be in the system's PATH."
+		)?;
+
+		#[cfg(not(target_os = "windows"))]
+		write!(
+			f,
+			"\nYou may need to add the directory of the current executable to the LD_LIBRARY_PATH environment variable."
+		)?;
+
+		Ok(())
+	}
+}
+impl std::error::Error for LoadError {}
 
 /// Creates a new bytecode compiler instance.
 ///
@@ -43,8 +226,23 @@ pub enum LuaError {
 ///
 /// ## Thread safety
 /// The bytecode compiler instance's Lua state is locked behind a Mutex to ensure concurrency safety.
+///
+/// ## Errors
+/// Returns `LuaError::LoadError` if `lua_shared` couldn't be found or loaded.
+///
+/// Use [`CompilerBuilder`](crate::CompilerBuilder) to configure where `lua_shared` is loaded from.
 pub fn compiler() -> Result<BytecodeCompiler, LuaError> {
-	unsafe { BytecodeCompiler::new() }
+	CompilerBuilder::new().build()
+}
+
+/// Creates a pool of bytecode compilers with one Lua state per available CPU, for compiling on multiple threads at once.
+///
+/// ## Errors
+/// Returns `LuaError::LoadError` if `lua_shared` couldn't be found or loaded.
+///
+/// Use [`CompilerBuilder`](crate::CompilerBuilder) to configure the size of the pool and where `lua_shared` is loaded from.
+pub fn compiler_pool() -> Result<CompilerPool, LuaError> {
+	CompilerBuilder::new().build_pool()
 }
 
 /// Converts a string literal to a Lua-compatible NUL terminated `CString`.
@@ -52,6 +250,15 @@ pub fn compiler() -> Result<BytecodeCompiler, LuaError> {
 /// Also can convert a `String` or `&str` to a Lua-compatible NUL terminated `CString`.
 ///
 /// **You must not add any NUL bytes into this string yourself.**
+///
+/// ## Panics
+/// Panics if a `String` or `&str` contains a NUL byte.
+///
+/// ## Safety
+/// When given a `String` or `&str`, the returned pointer points into a temporary `CString` that is dropped at the end of the enclosing statement.
+/// Only ever pass it straight to a function, e.g. `compiler.compile_string(lua_string!(src), false)`; storing it in a variable leaves it dangling.
+///
+/// The safe [`compile_str`](crate::BytecodeCompiler::compile_str), [`compile_bytes`](crate::BytecodeCompiler::compile_bytes) and [`compile_path`](crate::BytecodeCompiler::compile_path) don't need this macro at all.
 #[macro_export]
 macro_rules! lua_string {
 	( $str:literal ) => {
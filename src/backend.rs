@@ -0,0 +1,108 @@
+//! Where a [`BytecodeCompiler`](crate::BytecodeCompiler) gets LuaJIT from.
+//!
+//! By default, [`CompilerBuilder`](crate::CompilerBuilder) loads Garry's Mod's `lua_shared`, which compiles exactly the bytecode the game expects.
+//! As `lua_shared` can't be redistributed, [`LuaJit`] loads a stock LuaJIT library instead, and with the `luajit` feature [`LinkedLuaJit`]
+//! uses a LuaJIT built and linked into the executable, so that tests and CI can run without any of the game's binaries.
+//!
+//! Stock LuaJIT doesn't support Garry's Mod's additions to the language, such as `continue` and C-style comments and operators,
+//! so source code compiled with it is rewritten into standard Lua first (see [`glua`](crate::glua)). It must also be the same version
+//! and pointer width as the game's LuaJIT for its bytecode to be loadable.
+//!
+//! ```no_run
+//! use gluac_rs::{backend::LuaJit, CompilerBuilder};
+//!
+//! let compiler = CompilerBuilder::new().backend(LuaJit::new().library_path("/usr/local/lib")).build().unwrap();
+//! ```
+
+use std::path::PathBuf;
+
+use crate::{
+	library::{self, LibrarySearch},
+	lua::LuaApi,
+	LoadError,
+};
+
+/// Loads the Lua C API that compiles bytecode.
+///
+/// Implementations that load a library from disk can use [`LuaApi::load`].
+pub trait Backend: std::fmt::Debug + Send + Sync {
+	/// Loads the library, or returns it if it was already loaded.
+	fn load(&self) -> Result<&'static LuaApi, LoadError>;
+
+	/// Whether the library understands Garry's Mod's syntax extensions to Lua. If it doesn't, source code is rewritten by
+	/// [`glua::to_lua`](crate::glua::to_lua) before it's compiled, unless [`CompilerBuilder::glua`](crate::CompilerBuilder::glua) says otherwise.
+	fn supports_glua(&self) -> bool {
+		false
+	}
+}
+
+/// Garry's Mod's `lua_shared`, found as described in [`CompilerBuilder`](crate::CompilerBuilder). This is the default backend.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LuaShared(pub(crate) LibrarySearch);
+impl Backend for LuaShared {
+	fn load(&self) -> Result<&'static LuaApi, LoadError> {
+		LuaApi::load(self.0.candidates())
+	}
+
+	fn supports_glua(&self) -> bool {
+		true
+	}
+}
+
+/// File names of stock LuaJIT shared libraries.
+#[cfg(target_os = "windows")]
+const LUAJIT_NAMES: &[&str] = &["lua51.dll", "luajit.dll"];
+
+#[cfg(not(target_os = "windows"))]
+const LUAJIT_NAMES: &[&str] = &["libluajit-5.1.so.2", "libluajit-5.1.so", "libluajit.so"];
+
+/// A stock LuaJIT shared library, such as `libluajit-5.1.so` or `lua51.dll`.
+///
+/// The paths given to [`library_path`](LuaJit::library_path) are tried first, then the system's library search path.
+#[derive(Debug, Clone, Default)]
+pub struct LuaJit {
+	library_paths: Vec<PathBuf>,
+}
+impl LuaJit {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds a path to a LuaJIT library, or to a directory containing one.
+	///
+	/// These are tried in the order they were added.
+	pub fn library_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+		self.library_paths.push(path.into());
+		self
+	}
+
+	fn candidates(&self) -> Vec<PathBuf> {
+		let mut candidates = Vec::new();
+		for path in &self.library_paths {
+			library::push_candidates(&mut candidates, path, LUAJIT_NAMES);
+		}
+
+		// Let the dynamic linker search for it
+		candidates.extend(LUAJIT_NAMES.iter().map(PathBuf::from));
+		candidates
+	}
+}
+impl Backend for LuaJit {
+	fn load(&self) -> Result<&'static LuaApi, LoadError> {
+		LuaApi::load(self.candidates())
+	}
+}
+
+/// The LuaJIT built from source and statically linked into the executable by the `luajit` feature.
+///
+/// This never fails to load.
+#[cfg(feature = "luajit")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LinkedLuaJit;
+
+#[cfg(feature = "luajit")]
+impl Backend for LinkedLuaJit {
+	fn load(&self) -> Result<&'static LuaApi, LoadError> {
+		Ok(LuaApi::linked())
+	}
+}
@@ -0,0 +1,389 @@
+use std::{
+	error::Error,
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicUsize, Ordering},
+	time::Instant,
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use gluac_rs::{
+	bytecode::{self, Branch},
+	realm::{Ambiguity, Realm},
+	Bytecode, BytecodeCompiler, CompileCache,
+};
+
+use crate::report::{self, Format};
+
+pub(crate) type BuildError = Box<dyn Error + Send + Sync>;
+
+pub(crate) fn subcommand() -> clap::App<'static, 'static> {
+	source_args(clap::SubCommand::with_name("build"))
+		.about("Compiles every Lua file in a directory, mirroring its layout in the output directory")
+		.arg(
+			clap::Arg::with_name("cache")
+				.long("cache")
+				.help("Directory to cache compiled bytecode in, so unchanged files aren't compiled again [default: the user's cache directory]")
+				.takes_value(true)
+				.conflicts_with("no_cache"),
+		)
+		.arg(
+			clap::Arg::with_name("no_cache")
+				.long("no-cache")
+				.help("Compiles every file, without caching"),
+		)
+		.arg(
+			clap::Arg::with_name("jobs")
+				.long("jobs")
+				.short("j")
+				.help("Number of files to compile in parallel [default: number of CPUs]")
+				.takes_value(true),
+		)
+}
+
+/// Adds the arguments that choose which files to compile and where to put them, shared by `build` and `watch`.
+pub(crate) fn source_args(app: clap::App<'static, 'static>) -> clap::App<'static, 'static> {
+	app.arg(
+		clap::Arg::with_name("src_dir")
+			.help("Directory to compile, e.g. an addon's lua directory")
+			.required(true),
+	)
+	.arg(
+		clap::Arg::with_name("out_dir")
+			.short("o")
+			.help("Output directory")
+			.takes_value(true)
+			.required(true),
+	)
+	.arg(
+		clap::Arg::with_name("strip")
+			.long("strip")
+			.short("s")
+			.help("Strips debug information from the compiled bytecode"),
+	)
+	.arg(
+		clap::Arg::with_name("include")
+			.long("include")
+			.short("i")
+			.help("Only compiles files matching this glob, relative to the source directory [default: **/*.lua]")
+			.takes_value(true)
+			.multiple(true)
+			.number_of_values(1),
+	)
+	.arg(
+		clap::Arg::with_name("exclude")
+			.long("exclude")
+			.short("x")
+			.help("Skips files matching this glob, relative to the source directory")
+			.takes_value(true)
+			.multiple(true)
+			.number_of_values(1),
+	)
+	.arg(
+		clap::Arg::with_name("chunk_prefix")
+			.long("chunk-prefix")
+			.help("Prefixed to each file's path relative to the source directory to form its chunk name, e.g. addons/myaddon/lua/")
+			.takes_value(true),
+	)
+	.arg(crate::branch_arg(
+		"Translates the bytecode to the format loaded by this branch of Garry's Mod",
+	))
+	.arg(crate::translate_arg())
+	.arg(
+		clap::Arg::with_name("realms")
+			.long("realms")
+			.help("Splits the output into server and client directories, leaving server-only files out of the client's, by each file's sv_/cl_/sh_ prefix or autorun/server or autorun/client directory"),
+	)
+	.arg(report::format_arg())
+}
+
+/// What to compile and where to put it.
+pub(crate) struct BuildOptions {
+	pub(crate) src_dir: PathBuf,
+	pub(crate) out_dir: PathBuf,
+	pub(crate) strip_debug: bool,
+	pub(crate) branch: Branch,
+	pub(crate) chunk_prefix: String,
+	pub(crate) cache: Option<CompileCache>,
+
+	/// Whether to split the output into `server` and `client` directories.
+	pub(crate) realms: bool,
+	include: GlobSet,
+	exclude: GlobSet,
+}
+impl BuildOptions {
+	pub(crate) fn from_matches(matches: &clap::ArgMatches) -> Result<Self, Box<dyn Error>> {
+		let include: Vec<&str> = matches
+			.values_of("include")
+			.map(|globs| globs.collect())
+			.unwrap_or_else(|| vec!["**/*.lua"]);
+		let exclude: Vec<&str> = matches.values_of("exclude").map(|globs| globs.collect()).unwrap_or_default();
+
+		Ok(Self {
+			src_dir: PathBuf::from(matches.value_of_os("src_dir").unwrap()),
+			out_dir: PathBuf::from(matches.value_of_os("out_dir").unwrap()),
+			strip_debug: matches.is_present("strip"),
+			branch: crate::translate_to(matches)?.unwrap_or(Branch::NATIVE),
+			chunk_prefix: matches.value_of("chunk_prefix").unwrap_or_default().to_string(),
+			cache: if matches.is_present("no_cache") {
+				None
+			} else {
+				matches
+					.value_of_os("cache")
+					.map(PathBuf::from)
+					.or_else(default_cache_dir)
+					.map(CompileCache::new)
+			},
+			realms: matches.is_present("realms"),
+			include: glob_set(&include)?,
+			exclude: glob_set(&exclude)?,
+		})
+	}
+
+	/// Whether a file, given by its path relative to the source directory, should be compiled.
+	pub(crate) fn is_included(&self, relative_path: &Path) -> bool {
+		let relative_path = slash_path(relative_path);
+		self.include.is_match(&relative_path) && !self.exclude.is_match(&relative_path)
+	}
+
+	/// Returns the path of every file to compile relative to the source directory, sorted.
+	pub(crate) fn sources(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+		let mut sources = Vec::new();
+		for entry in walkdir::WalkDir::new(&self.src_dir) {
+			let entry = entry?;
+			if !entry.file_type().is_file() {
+				continue;
+			}
+
+			let relative_path = entry.path().strip_prefix(&self.src_dir)?;
+			if self.is_included(relative_path) {
+				sources.push(relative_path.to_path_buf());
+			}
+		}
+		sources.sort();
+		Ok(sources)
+	}
+
+	/// The chunk name a file is compiled with, which is how Garry's Mod would refer to it in errors, e.g. `@addons/myaddon/lua/autorun/init.lua`
+	pub(crate) fn chunk_name(&self, relative_path: &Path) -> String {
+		format!("@{}{}", self.chunk_prefix, slash_path(relative_path))
+	}
+
+	/// Compiles a file, given by its path relative to the source directory.
+	pub(crate) fn compile(&self, compiler: &BytecodeCompiler, relative_path: &Path) -> Result<Bytecode, BuildError> {
+		let src = std::fs::read(self.src_dir.join(relative_path))?;
+		let chunk_name = self.chunk_name(relative_path);
+		let bytecode = match &self.cache {
+			Some(cache) => cache.compile_named(compiler, src, &chunk_name, self.strip_debug)?,
+			None => compiler.compile_named(src, &chunk_name, self.strip_debug)?,
+		};
+		if self.branch == Branch::NATIVE {
+			Ok(bytecode)
+		} else {
+			Ok(bytecode::translate(&bytecode, self.branch)?)
+		}
+	}
+
+	/// The realm a file runs in.
+	///
+	/// Garry's Mod runs `autorun` files by their directory whatever their name, so that decides conflicts. Files that don't follow any convention
+	/// are assumed to be shared, so that they end up in both output directories.
+	pub(crate) fn realm(&self, relative_path: &Path) -> Realm {
+		match Realm::classify(relative_path) {
+			Ok(realm) | Err(Ambiguity::Conflict { folder: realm, .. }) => realm,
+			Err(Ambiguity::Unknown) => Realm::Shared,
+		}
+	}
+
+	/// Where a file's bytecode is written: the same path in the output directory, or in its `server` and `client` directories if splitting by realm.
+	pub(crate) fn out_paths(&self, relative_path: &Path) -> Vec<PathBuf> {
+		if !self.realms {
+			return vec![self.out_dir.join(relative_path)];
+		}
+
+		let realm = self.realm(relative_path);
+		let mut out_paths = Vec::with_capacity(2);
+		if realm.is_server() {
+			out_paths.push(self.out_dir.join("server").join(relative_path));
+		}
+		if realm.is_client() {
+			out_paths.push(self.out_dir.join("client").join(relative_path));
+		}
+		out_paths
+	}
+
+	/// Prints a warning if splitting by realm and a file's realm is ambiguous.
+	pub(crate) fn warn_ambiguous_realm(&self, relative_path: &Path) {
+		if let (true, Err(ambiguity)) = (self.realms, Realm::classify(relative_path)) {
+			eprintln!(
+				"warning: {}: {}, so it's treated as {}",
+				slash_path(relative_path),
+				ambiguity,
+				self.realm(relative_path)
+			);
+		}
+	}
+
+	/// Compiles a file and writes its bytecode to its output paths.
+	pub(crate) fn build_file(&self, compiler: &BytecodeCompiler, relative_path: &Path) -> Result<Bytecode, BuildError> {
+		let bytecode = self.compile(compiler, relative_path)?;
+
+		for out_path in self.out_paths(relative_path) {
+			if let Some(parent) = out_path.parent() {
+				std::fs::create_dir_all(parent)?;
+			}
+			std::fs::write(out_path, &bytecode)?;
+		}
+
+		Ok(bytecode)
+	}
+}
+
+/// The result of building a single file.
+pub(crate) struct BuildOutcome {
+	pub(crate) path: PathBuf,
+	pub(crate) result: Result<Bytecode, BuildError>,
+}
+
+/// Builds every file in parallel, returning their outcomes in the same order as `sources`, which are sorted by path.
+pub(crate) fn build_all(options: &BuildOptions, pool: &gluac_rs::CompilerPool, sources: &[PathBuf]) -> Vec<BuildOutcome> {
+	for_each_parallel(pool, sources, |compiler, path| BuildOutcome {
+		path: path.to_path_buf(),
+		result: options.build_file(compiler, path),
+	})
+}
+
+/// Runs `f` on every file in parallel, each thread with its own compiler, returning the results in the same order as `sources`.
+pub(crate) fn for_each_parallel<R, F>(pool: &gluac_rs::CompilerPool, sources: &[PathBuf], f: F) -> Vec<R>
+where
+	R: Send,
+	F: Fn(&BytecodeCompiler, &Path) -> R + Sync,
+{
+	let next = AtomicUsize::new(0);
+	let mut results = std::thread::scope(|scope| {
+		let workers = (0..pool.size().min(sources.len()))
+			.map(|_| {
+				scope.spawn(|| {
+					let compiler = pool.get();
+					let mut results = Vec::new();
+					loop {
+						let index = next.fetch_add(1, Ordering::Relaxed);
+						match sources.get(index) {
+							Some(path) => results.push((index, f(&compiler, path))),
+							None => break results,
+						}
+					}
+				})
+			})
+			.collect::<Vec<_>>();
+
+		workers
+			.into_iter()
+			.flat_map(|worker| worker.join().expect("Worker panicked"))
+			.collect::<Vec<_>>()
+	});
+
+	results.sort_by_key(|(index, _)| *index);
+	results.into_iter().map(|(_, result)| result).collect()
+}
+
+pub(crate) fn run(matches: &clap::ArgMatches) -> Result<(), Box<dyn Error>> {
+	let options = BuildOptions::from_matches(matches)?;
+	let format = Format::from_matches(matches);
+
+	let mut builder = gluac_rs::CompilerBuilder::new();
+	if let Some(jobs) = matches.value_of("jobs") {
+		builder = builder.pool_size(jobs.parse().map_err(|_| format!("invalid number of jobs: {}", jobs))?);
+	}
+	let pool = builder.build_pool()?;
+
+	let start = Instant::now();
+	let sources = options.sources()?;
+	for path in &sources {
+		options.warn_ambiguous_realm(path);
+	}
+	let outcomes = build_all(&options, &pool, &sources);
+
+	let mut failed = 0;
+	for outcome in &outcomes {
+		let error = outcome.result.as_ref().err().map(|error| &**error as &(dyn Error + 'static));
+		if error.is_some() {
+			failed += 1;
+		}
+		match format {
+			Format::Json => report::print_json(Some(&options.src_dir.join(&outcome.path)), outcome.result.as_deref().ok(), error),
+			Format::Text => {
+				if let Some(error) = error {
+					report_error(&outcome.path, error);
+				}
+			}
+		}
+	}
+
+	eprintln!(
+		"Compiled {} of {} files ({} failed, {} unchanged) in {:.2}s",
+		outcomes.len() - failed,
+		outcomes.len(),
+		failed,
+		options.cache.as_ref().map(CompileCache::hits).unwrap_or(0),
+		start.elapsed().as_secs_f64()
+	);
+
+	if options.realms {
+		let count = |realm| sources.iter().filter(|path| options.realm(path) == realm).count();
+		eprintln!(
+			"{} server, {} client and {} shared files ({} ambiguous)",
+			count(Realm::Server),
+			count(Realm::Client),
+			count(Realm::Shared),
+			sources.iter().filter(|path| Realm::classify(path).is_err()).count()
+		);
+	}
+
+	report::FilesFailed::check(
+		outcomes.len(),
+		outcomes
+			.iter()
+			.filter_map(|outcome| outcome.result.as_ref().err())
+			.map(|error| &**error as _),
+	)?;
+	Ok(())
+}
+
+/// Prints why a file failed to build. Syntax errors already say which file they're in.
+pub(crate) fn report_error(path: &Path, error: &(dyn Error + 'static)) {
+	match error.downcast_ref::<gluac_rs::LuaError>().and_then(gluac_rs::LuaError::diagnostic) {
+		Some(diagnostic) => eprintln!("error: {}", diagnostic),
+		None => eprintln!("error: {}: {}", path.display(), error),
+	}
+}
+
+/// The default directory to cache compiled bytecode in.
+#[cfg(target_os = "windows")]
+fn default_cache_dir() -> Option<PathBuf> {
+	std::env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("gluac").join("cache"))
+}
+
+/// The default directory to cache compiled bytecode in.
+#[cfg(not(target_os = "windows"))]
+fn default_cache_dir() -> Option<PathBuf> {
+	std::env::var_os("XDG_CACHE_HOME")
+		.map(PathBuf::from)
+		.or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+		.map(|dir| dir.join("gluac"))
+}
+
+fn glob_set(globs: &[&str]) -> Result<GlobSet, globset::Error> {
+	let mut builder = GlobSetBuilder::new();
+	for glob in globs {
+		builder.add(Glob::new(glob)?);
+	}
+	builder.build()
+}
+
+/// Formats a relative path with `/` separators, like Garry's Mod does.
+pub(crate) fn slash_path(path: &Path) -> String {
+	path.components()
+		.map(|component| component.as_os_str().to_string_lossy())
+		.collect::<Vec<_>>()
+		.join("/")
+}
@@ -0,0 +1,90 @@
+use std::{error::Error, path::PathBuf, time::Instant};
+
+use crate::{
+	build,
+	report::{self, Format},
+};
+
+pub(crate) fn subcommand() -> clap::App<'static, 'static> {
+	clap::SubCommand::with_name("check")
+		.about("Checks Lua files for syntax errors without compiling them, reporting every error found")
+		.arg(
+			clap::Arg::with_name("paths")
+				.help("Files to check, or directories to check every .lua file in")
+				.required(true)
+				.multiple(true),
+		)
+		.arg(
+			clap::Arg::with_name("jobs")
+				.long("jobs")
+				.short("j")
+				.help("Number of files to check in parallel [default: number of CPUs]")
+				.takes_value(true),
+		)
+		.arg(report::format_arg())
+}
+
+/// Expands directories into the .lua files inside them, sorted. Files are kept as given, whatever their extension.
+fn sources(matches: &clap::ArgMatches) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+	let mut sources = Vec::new();
+	for path in matches.values_of_os("paths").unwrap().map(PathBuf::from) {
+		if !path.is_dir() {
+			sources.push(path);
+			continue;
+		}
+
+		let mut files = Vec::new();
+		for entry in walkdir::WalkDir::new(&path) {
+			let entry = entry?;
+			if entry.file_type().is_file() && entry.path().extension() == Some("lua".as_ref()) {
+				files.push(entry.into_path());
+			}
+		}
+		files.sort();
+		sources.extend(files);
+	}
+	Ok(sources)
+}
+
+pub(crate) fn run(matches: &clap::ArgMatches) -> Result<(), Box<dyn Error>> {
+	let mut builder = gluac_rs::CompilerBuilder::new();
+	if let Some(jobs) = matches.value_of("jobs") {
+		builder = builder.pool_size(jobs.parse().map_err(|_| format!("invalid number of jobs: {}", jobs))?);
+	}
+	let pool = builder.build_pool()?;
+	let format = Format::from_matches(matches);
+
+	let start = Instant::now();
+	let sources = sources(matches)?;
+
+	let results = build::for_each_parallel(&pool, &sources, |compiler, path| compiler.check_path(path));
+
+	let mut failed = 0;
+	for (path, result) in sources.iter().zip(&results) {
+		let error = result.as_ref().err();
+		if error.is_some() {
+			failed += 1;
+		}
+		match format {
+			Format::Json => report::print_json(Some(path), None, error.map(|error| error as &(dyn Error + 'static))),
+			Format::Text => {
+				if let Some(error) = error {
+					build::report_error(path, error);
+				}
+			}
+		}
+	}
+
+	eprintln!(
+		"Checked {} files ({} failed) in {:.2}s",
+		results.len(),
+		failed,
+		start.elapsed().as_secs_f64()
+	);
+
+	report::FilesFailed::check(
+		results.len(),
+		results.iter().filter_map(|result| result.as_ref().err()).map(|error| error as _),
+	)?;
+	Ok(())
+}
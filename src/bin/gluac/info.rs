@@ -0,0 +1,75 @@
+use std::{
+	error::Error,
+	path::{Path, PathBuf},
+};
+
+use gluac_rs::bytecode::{self, Branch, Header};
+
+use crate::report::{FilesFailed, PathError};
+
+pub(crate) fn subcommand() -> clap::App<'static, 'static> {
+	clap::SubCommand::with_name("info")
+		.about("Shows the header of bytecode files, and which branch of Garry's Mod can load them")
+		.arg(
+			clap::Arg::with_name("paths")
+				.help("Bytecode files to inspect")
+				.required(true)
+				.multiple(true),
+		)
+		.arg(crate::branch_arg("Fails if any of the files can't be loaded by this branch"))
+}
+
+fn read_header(path: &Path) -> Result<Header, Box<dyn Error>> {
+	let data = std::fs::read(path).map_err(PathError::new(path))?;
+	Ok(bytecode::header(&data).map_err(|error| format!("{}: {}", path.display(), error))?)
+}
+
+pub(crate) fn run(matches: &clap::ArgMatches) -> Result<(), Box<dyn Error>> {
+	let branch = crate::branch_of(matches);
+
+	let paths = matches.values_of_os("paths").unwrap().map(PathBuf::from).collect::<Vec<_>>();
+	let mut errors: Vec<Box<dyn Error>> = Vec::new();
+	for path in &paths {
+		let header = match read_header(path) {
+			Ok(header) => header,
+			Err(error) => {
+				eprintln!("error: {}", error);
+				errors.push(error);
+				continue;
+			}
+		};
+
+		let mut flags = Vec::new();
+		if header.is_stripped() {
+			flags.push("stripped");
+		}
+		if header.has_ffi() {
+			flags.push("FFI");
+		}
+		if header.is_big_endian() {
+			flags.push("big-endian");
+		}
+		if header.is_fr2() {
+			flags.push("FR2");
+		}
+
+		println!(
+			"{}: version {}, flags: {}, loadable by: {}",
+			path.display(),
+			header.version,
+			if flags.is_empty() { "none".to_string() } else { flags.join(", ") },
+			Branch::of(&header).map_or_else(|| "neither branch".to_string(), |branch| format!("the {}", branch))
+		);
+		if let Some(chunk_name) = &header.chunk_name {
+			println!("  chunk name: {}", String::from_utf8_lossy(chunk_name));
+		}
+
+		if let Some(Err(incompatibility)) = branch.map(|branch| header.check_branch(branch)) {
+			eprintln!("error: {}: {}", path.display(), incompatibility);
+			errors.push(incompatibility.into());
+		}
+	}
+
+	FilesFailed::check(paths.len(), errors.iter().map(|error| &**error))?;
+	Ok(())
+}
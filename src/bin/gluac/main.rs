@@ -0,0 +1,249 @@
+mod build;
+mod check;
+mod info;
+mod pack;
+mod report;
+mod unpack;
+mod watch;
+
+use report::PathError;
+
+fn main() {
+	let matches = clap::App::new("gluac")
+		.version(env!("CARGO_PKG_VERSION"))
+		.about("Compiles Garry's Mod Lua source code to bytecode")
+		.author("William Venner <william@venner.io>")
+		.setting(clap::AppSettings::SubcommandsNegateReqs)
+		.setting(clap::AppSettings::ArgsNegateSubcommands)
+		.arg(
+			clap::Arg::with_name("strip")
+				.long("strip")
+				.short("s")
+				.help("Strips debug information from the compiled bytecode")
+				.multiple(false),
+		)
+		.arg(
+			clap::Arg::with_name("file")
+				.short("f")
+				.help("Input file path, or - to read from standard input")
+				.takes_value(true)
+				.multiple(false),
+		)
+		.arg(
+			clap::Arg::with_name("stdin")
+				.long("stdin")
+				.help("Reads the source code from standard input"),
+		)
+		.arg(clap::Arg::with_name("input").help("The input source code").multiple(true).raw(true))
+		.group(clap::ArgGroup::with_name("source").args(&["file", "stdin", "input"]).required(true))
+		.arg(
+			clap::Arg::with_name("chunk_name")
+				.long("chunk-name")
+				.short("n")
+				.help("Chunk name to compile the source code with, e.g. @addons/myaddon/lua/autorun/init.lua")
+				.takes_value(true)
+				.multiple(false),
+		)
+		.arg(
+			clap::Arg::with_name("output")
+				.short("o")
+				.help("Output file path")
+				.takes_value(true)
+				.multiple(false)
+				.required(false),
+		)
+		.arg(branch_arg("Translates the bytecode to the format loaded by this branch of Garry's Mod"))
+		.arg(translate_arg())
+		.arg(report::format_arg())
+		.subcommand(
+			clap::SubCommand::with_name("disasm")
+				.about("Disassembles bytecode, compiling the file first if it's Lua source code")
+				.arg(
+					clap::Arg::with_name("strip")
+						.long("strip")
+						.short("s")
+						.help("Strips debug information when compiling Lua source code"),
+				)
+				.arg(
+					clap::Arg::with_name("path")
+						.help("Bytecode or Lua source file path, or - to read from standard input")
+						.required(true),
+				)
+				.arg(clap::Arg::with_name("output").short("o").help("Output file path").takes_value(true)),
+		)
+		.subcommand(build::subcommand())
+		.subcommand(watch::subcommand())
+		.subcommand(check::subcommand())
+		.subcommand(info::subcommand())
+		.subcommand(pack::subcommand())
+		.subcommand(unpack::subcommand())
+		.get_matches();
+
+	let result = match matches.subcommand() {
+		("disasm", Some(matches)) => disasm(matches),
+		("build", Some(matches)) => build::run(matches),
+		("watch", Some(matches)) => watch::run(matches),
+		("check", Some(matches)) => check::run(matches),
+		("info", Some(matches)) => info::run(matches),
+		("pack", Some(matches)) => pack::run(matches),
+		("unpack", Some(matches)) => unpack::run(matches),
+		_ => compile(&matches),
+	};
+
+	if let Err(error) = result {
+		eprintln!("error: {}", error);
+		std::process::exit(report::ExitCode::of(&*error) as i32);
+	}
+}
+
+fn compile(matches: &clap::ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+	let format = report::Format::from_matches(matches);
+	if format == report::Format::Json && !matches.is_present("output") {
+		return Err("--format json prints results to stdout, so the bytecode must be written to a file with -o".into());
+	}
+
+	let result = compile_source(matches);
+
+	if format == report::Format::Json {
+		let path = matches.value_of_os("file").filter(|path| *path != "-").map(std::path::Path::new);
+		report::print_json(path, result.as_deref().ok(), result.as_ref().err().map(|error| &**error));
+	}
+
+	write_output(matches, &result?)
+}
+
+fn compile_source(matches: &clap::ArgMatches) -> Result<gluac_rs::Bytecode, Box<dyn std::error::Error>> {
+	let strip_debug = matches.is_present("strip");
+
+	let chunk_name = matches.value_of("chunk_name");
+	let translate_to = translate_to(matches)?;
+
+	let compiler = gluac_rs::compiler()?;
+	let bytecode = if let Some(src) = matches.values_of_os("input") {
+		// Arguments are joined back together with spaces, like a shell would have split them
+		let src = src.map(os_str_bytes).collect::<Vec<_>>().join(&b' ');
+		match chunk_name {
+			Some(chunk_name) => compiler.compile_named(src, chunk_name, strip_debug)?,
+			None => compiler.compile_bytes(src, strip_debug)?,
+		}
+	} else if matches.is_present("stdin") || matches.value_of_os("file") == Some("-".as_ref()) {
+		compiler.compile_named(read_stdin()?, chunk_name.unwrap_or("=stdin"), strip_debug)?
+	} else if let Some(path) = matches.value_of_os("file") {
+		if let Some(chunk_name) = chunk_name {
+			compiler.compile_named(std::fs::read(path).map_err(PathError::new(path))?, chunk_name, strip_debug)?
+		} else {
+			compiler.compile_path(path, strip_debug)?
+		}
+	} else {
+		unreachable!();
+	};
+
+	Ok(match translate_to {
+		Some(branch) => gluac_rs::bytecode::translate(&bytecode, branch)?,
+		None => bytecode,
+	})
+}
+
+fn disasm(matches: &clap::ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+	let path = matches.value_of_os("path").unwrap();
+	let data = if path == "-" {
+		read_stdin()?
+	} else {
+		std::fs::read(path).map_err(PathError::new(path))?
+	};
+
+	let listing = if data.starts_with(gluac_rs::bytecode::SIGNATURE) {
+		gluac_rs::bytecode::disassemble(&data)?
+	} else {
+		let compiler = gluac_rs::compiler()?;
+		let strip_debug = matches.is_present("strip");
+		let bytecode = if path == "-" {
+			compiler.compile_named(data, "=stdin", strip_debug)?
+		} else {
+			compiler.compile_path(path, strip_debug)?
+		};
+		gluac_rs::bytecode::disassemble(&bytecode)?
+	};
+
+	write_output(matches, listing.as_bytes())
+}
+
+/// The `--branch` argument, choosing a branch of Garry's Mod.
+pub(crate) fn branch_arg(help: &'static str) -> clap::Arg<'static, 'static> {
+	clap::Arg::with_name("branch")
+		.long("branch")
+		.short("b")
+		.help(help)
+		.takes_value(true)
+		.possible_values(&["x86", "x86-64"])
+}
+
+pub(crate) fn branch_of(matches: &clap::ArgMatches) -> Option<gluac_rs::bytecode::Branch> {
+	use gluac_rs::bytecode::Branch;
+
+	match matches.value_of("branch") {
+		Some("x86") => Some(Branch::X86),
+		Some("x86-64") => Some(Branch::X86_64),
+		_ => None,
+	}
+}
+
+/// The `--translate` argument, which opts in to translating bytecode for a `--branch` other than the one `lua_shared` is from.
+pub(crate) fn translate_arg() -> clap::Arg<'static, 'static> {
+	clap::Arg::with_name("translate")
+		.long("translate")
+		.requires("branch")
+		.help("Allows translating the bytecode when --branch isn't the branch lua_shared is from. Experimental: translated bytecode hasn't been checked against bytecode compiled by the other branch")
+}
+
+/// The branch bytecode has to be translated to, if `--branch` isn't the branch `lua_shared` is from.
+///
+/// Translating is experimental, so this fails unless `--translate` is given too.
+pub(crate) fn translate_to(matches: &clap::ArgMatches) -> Result<Option<gluac_rs::bytecode::Branch>, String> {
+	use gluac_rs::bytecode::Branch;
+
+	match branch_of(matches) {
+		Some(branch) if branch != Branch::NATIVE && !matches.is_present("translate") => Err(format!(
+			"lua_shared compiles bytecode for the {}, so it would have to be translated for the {}, which is experimental. Pass --translate to do it anyway",
+			Branch::NATIVE,
+			branch
+		)),
+		Some(branch) if branch != Branch::NATIVE => Ok(Some(branch)),
+		_ => Ok(None),
+	}
+}
+
+/// Reads all of standard input, which may be binary.
+fn read_stdin() -> std::io::Result<Vec<u8>> {
+	use std::io::Read;
+
+	let mut data = Vec::new();
+	std::io::stdin().lock().read_to_end(&mut data)?;
+	Ok(data)
+}
+
+/// The bytes of a command line argument. Outside of Unix, arguments that aren't valid Unicode are converted lossily.
+#[cfg(unix)]
+fn os_str_bytes(os_str: &std::ffi::OsStr) -> Vec<u8> {
+	use std::os::unix::ffi::OsStrExt;
+	os_str.as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn os_str_bytes(os_str: &std::ffi::OsStr) -> Vec<u8> {
+	os_str.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Writes to the file given by the `output` argument, or to stdout if there isn't one.
+fn write_output(matches: &clap::ArgMatches, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+	use std::io::Write;
+
+	if let Some(output) = matches.value_of_os("output") {
+		std::fs::write(output, data).map_err(PathError::new(output))?;
+	} else {
+		let mut stdout = std::io::stdout();
+		stdout.write_all(data)?;
+		stdout.flush()?;
+	}
+	Ok(())
+}
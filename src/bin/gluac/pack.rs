@@ -0,0 +1,180 @@
+use std::{
+	error::Error,
+	path::{Path, PathBuf},
+	time::Instant,
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use gluac_rs::{
+	bytecode,
+	gma::{Gma, GmaFile},
+	Bytecode, BytecodeCompiler,
+};
+
+use crate::{
+	build::{self, BuildError},
+	report::{FilesFailed, PathError},
+};
+
+pub(crate) fn subcommand() -> clap::App<'static, 'static> {
+	clap::SubCommand::with_name("pack")
+		.about("Packs an addon into a .gma archive, compiling the Lua files in its lua directory")
+		.arg(
+			clap::Arg::with_name("addon_dir")
+				.help("Addon directory, containing addon.json")
+				.required(true),
+		)
+		.arg(
+			clap::Arg::with_name("output")
+				.short("o")
+				.help("Output .gma file path")
+				.takes_value(true)
+				.required(true),
+		)
+		.arg(
+			clap::Arg::with_name("strip")
+				.long("strip")
+				.short("s")
+				.help("Strips debug information from the compiled bytecode"),
+		)
+		.arg(crate::branch_arg(
+			"Translates the bytecode to the format loaded by this branch of Garry's Mod",
+		))
+		.arg(crate::translate_arg())
+		.arg(
+			clap::Arg::with_name("jobs")
+				.long("jobs")
+				.short("j")
+				.help("Number of files to compile in parallel [default: number of CPUs]")
+				.takes_value(true),
+		)
+}
+
+/// The parts of an addon's `addon.json` that go into its archive.
+struct AddonJson {
+	title: String,
+	addon_type: String,
+	tags: Vec<String>,
+	description: String,
+
+	/// Files that aren't packed, matched against their path relative to the addon directory.
+	ignore: GlobSet,
+}
+impl AddonJson {
+	fn read(addon_dir: &Path) -> Result<Self, Box<dyn Error>> {
+		let path = addon_dir.join("addon.json");
+		let json = std::fs::read(&path).map_err(PathError::new(&path))?;
+		let json: serde_json::Value = serde_json::from_slice(&json).map_err(|error| format!("{}: {}", path.display(), error))?;
+
+		let string = |key| json.get(key).and_then(serde_json::Value::as_str).map(str::to_string);
+		let strings = |key| -> Vec<String> {
+			json.get(key)
+				.and_then(serde_json::Value::as_array)
+				.map(|values| values.iter().filter_map(serde_json::Value::as_str).map(str::to_string).collect())
+				.unwrap_or_default()
+		};
+
+		let mut ignore = GlobSetBuilder::new();
+		for glob in strings("ignore") {
+			ignore.add(Glob::new(&glob)?);
+		}
+
+		Ok(Self {
+			title: string("title").ok_or_else(|| format!("{}: missing \"title\"", path.display()))?,
+			addon_type: string("type").unwrap_or_default(),
+			tags: strings("tags"),
+			description: string("description").unwrap_or_default(),
+			ignore: ignore.build()?,
+		})
+	}
+}
+
+/// Returns the path of every file to pack relative to the addon directory, sorted.
+///
+/// `addon.json` itself, hidden files (such as `.git`) and files ignored by `addon.json` are skipped.
+fn sources(addon_dir: &Path, addon_json: &AddonJson) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+	let mut sources = Vec::new();
+	let walker = walkdir::WalkDir::new(addon_dir)
+		.into_iter()
+		.filter_entry(|entry| entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.'));
+	for entry in walker {
+		let entry = entry?;
+		if !entry.file_type().is_file() {
+			continue;
+		}
+
+		let relative_path = entry.path().strip_prefix(addon_dir)?;
+		if relative_path != Path::new("addon.json") && !addon_json.ignore.is_match(build::slash_path(relative_path)) {
+			sources.push(relative_path.to_path_buf());
+		}
+	}
+	sources.sort();
+	Ok(sources)
+}
+
+/// The path of a file in the archive, which Garry's Mod expects to be lowercase.
+fn gma_path(relative_path: &Path) -> String {
+	build::slash_path(relative_path).to_lowercase()
+}
+
+/// Reads a file to pack, compiling it if it's a Lua file in the `lua` directory.
+fn pack_file(matches: &clap::ArgMatches, compiler: &BytecodeCompiler, addon_dir: &Path, relative_path: &Path) -> Result<Bytecode, BuildError> {
+	let data = std::fs::read(addon_dir.join(relative_path))?;
+	if !relative_path.starts_with("lua") || relative_path.extension() != Some("lua".as_ref()) {
+		return Ok(data);
+	}
+
+	// Garry's Mod names the files of mounted addons by their path in the archive
+	let bytecode = compiler.compile_named(data, &format!("@{}", gma_path(relative_path)), matches.is_present("strip"))?;
+	match crate::translate_to(matches)? {
+		Some(branch) => Ok(bytecode::translate(&bytecode, branch)?),
+		None => Ok(bytecode),
+	}
+}
+
+pub(crate) fn run(matches: &clap::ArgMatches) -> Result<(), Box<dyn Error>> {
+	let addon_dir = PathBuf::from(matches.value_of_os("addon_dir").unwrap());
+	let output = PathBuf::from(matches.value_of_os("output").unwrap());
+	let addon_json = AddonJson::read(&addon_dir)?;
+	crate::translate_to(matches)?;
+
+	let mut builder = gluac_rs::CompilerBuilder::new();
+	if let Some(jobs) = matches.value_of("jobs") {
+		builder = builder.pool_size(jobs.parse().map_err(|_| format!("invalid number of jobs: {}", jobs))?);
+	}
+	let pool = builder.build_pool()?;
+
+	let start = Instant::now();
+	let sources = sources(&addon_dir, &addon_json)?;
+	let outcomes = build::for_each_parallel(&pool, &sources, |compiler, path| build::BuildOutcome {
+		path: path.to_path_buf(),
+		result: pack_file(matches, compiler, &addon_dir, path),
+	});
+
+	let mut gma = Gma::new(addon_json.title);
+	gma.addon_type = addon_json.addon_type;
+	gma.tags = addon_json.tags;
+	gma.description = addon_json.description;
+
+	let mut errors = Vec::new();
+	for outcome in outcomes {
+		match outcome.result {
+			Ok(data) => gma.files.push(GmaFile::new(gma_path(&outcome.path), data)),
+			Err(error) => {
+				build::report_error(&outcome.path, &*error);
+				errors.push(error);
+			}
+		}
+	}
+	FilesFailed::check(sources.len(), errors.iter().map(|error| &**error as _))?;
+
+	std::fs::write(&output, gma.write()?).map_err(PathError::new(&output))?;
+
+	eprintln!(
+		"Packed {} files into {} in {:.2}s",
+		gma.files.len(),
+		output.display(),
+		start.elapsed().as_secs_f64()
+	);
+	Ok(())
+}
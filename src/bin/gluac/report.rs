@@ -0,0 +1,158 @@
+use std::{
+	error::Error,
+	path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+/// How results are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+	/// Human-readable errors on stderr.
+	Text,
+
+	/// One JSON object per file on stdout.
+	Json,
+}
+impl Format {
+	pub(crate) fn from_matches(matches: &clap::ArgMatches) -> Self {
+		match matches.value_of("format") {
+			Some("json") => Format::Json,
+			_ => Format::Text,
+		}
+	}
+}
+
+pub(crate) fn format_arg() -> clap::Arg<'static, 'static> {
+	clap::Arg::with_name("format")
+		.long("format")
+		.help("How to print results: text, or json for one JSON object per file on stdout")
+		.takes_value(true)
+		.possible_values(&["text", "json"])
+		.default_value("text")
+}
+
+/// Prints the result of compiling or checking a file as a line of JSON.
+///
+/// `bytecode` is `None` if the file was only checked for syntax errors.
+pub(crate) fn print_json(path: Option<&Path>, bytecode: Option<&[u8]>, error: Option<&(dyn Error + 'static)>) {
+	let diagnostics = error
+		.and_then(|error| error.downcast_ref::<gluac_rs::LuaError>())
+		.and_then(gluac_rs::LuaError::diagnostic)
+		.map(|diagnostic| {
+			serde_json::json!({
+				"chunk": diagnostic.chunk,
+				"line": diagnostic.line,
+				"message": diagnostic.message,
+				"near": diagnostic.near_token,
+			})
+		});
+
+	let line = serde_json::json!({
+		"path": path.map(|path| path.to_string_lossy()),
+		"success": error.is_none(),
+		"size": bytecode.map(<[u8]>::len),
+		"sha256": bytecode.map(|bytecode| hex(&Sha256::digest(bytecode))),
+		"error": error.map(ToString::to_string),
+		"diagnostics": diagnostics.into_iter().collect::<Vec<_>>(),
+	});
+	println!("{}", line);
+}
+
+/// gluac's exit codes, so that scripts can tell why it failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ExitCode {
+	/// Anything else, including invalid arguments.
+	Failure = 1,
+
+	/// Lua source code has a syntax error.
+	SyntaxError = 2,
+
+	/// A file couldn't be read or written.
+	IoError = 3,
+
+	/// `lua_shared` couldn't be found or loaded.
+	LoadError = 4,
+}
+impl ExitCode {
+	pub(crate) fn of(error: &(dyn Error + 'static)) -> Self {
+		use gluac_rs::LuaError;
+
+		if let Some(error) = error.downcast_ref::<LuaError>() {
+			match error {
+				LuaError::SyntaxError(_) => ExitCode::SyntaxError,
+				LuaError::FileError(_) | LuaError::IoError(_) => ExitCode::IoError,
+				LuaError::LoadError(_) => ExitCode::LoadError,
+				_ => ExitCode::Failure,
+			}
+		} else if let Some(error) = error.downcast_ref::<FilesFailed>() {
+			error.exit_code
+		} else if error.is::<std::io::Error>() || error.is::<PathError>() || error.is::<walkdir::Error>() {
+			ExitCode::IoError
+		} else {
+			ExitCode::Failure
+		}
+	}
+}
+
+/// Returned when some files failed to compile or check, after each of their errors has been reported.
+#[derive(Debug)]
+pub(crate) struct FilesFailed {
+	failed: usize,
+	total: usize,
+	exit_code: ExitCode,
+}
+impl FilesFailed {
+	/// Returns an error if any files failed. Syntax errors take precedence when deciding the exit code.
+	pub(crate) fn check<'a, I>(total: usize, errors: I) -> Result<(), Self>
+	where
+		I: IntoIterator<Item = &'a (dyn Error + 'static)>,
+	{
+		let mut failed = 0;
+		let mut exit_code = None;
+		for error in errors {
+			failed += 1;
+			let code = ExitCode::of(error);
+			if exit_code.is_none() || code == ExitCode::SyntaxError {
+				exit_code = Some(code);
+			}
+		}
+
+		match exit_code {
+			Some(exit_code) => Err(FilesFailed { failed, total, exit_code }),
+			None => Ok(()),
+		}
+	}
+}
+impl std::fmt::Display for FilesFailed {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} of {} files failed", self.failed, self.total)
+	}
+}
+impl Error for FilesFailed {}
+
+/// An I/O error, with the path of the file it happened to.
+#[derive(Debug)]
+pub(crate) struct PathError {
+	path: PathBuf,
+	error: std::io::Error,
+}
+impl PathError {
+	pub(crate) fn new<P: Into<PathBuf>>(path: P) -> impl FnOnce(std::io::Error) -> Self {
+		move |error| PathError { path: path.into(), error }
+	}
+}
+impl std::fmt::Display for PathError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}: {}", self.path.display(), self.error)
+	}
+}
+impl Error for PathError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		Some(&self.error)
+	}
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,86 @@
+use std::{
+	error::Error,
+	path::{Component, Path, PathBuf},
+};
+
+use gluac_rs::gma::Gma;
+
+use crate::report::PathError;
+
+pub(crate) fn subcommand() -> clap::App<'static, 'static> {
+	clap::SubCommand::with_name("unpack")
+		.about("Lists the contents of a .gma archive, or extracts them")
+		.arg(clap::Arg::with_name("path").help(".gma file path").required(true))
+		.arg(
+			clap::Arg::with_name("output")
+				.short("o")
+				.help("Directory to extract the files and an addon.json into, instead of listing them")
+				.takes_value(true),
+		)
+}
+
+/// Prints the archive's metadata and the size and path of each file.
+fn list(gma: &Gma) {
+	println!("{}", gma.name);
+	if !gma.addon_type.is_empty() {
+		println!("  type: {}", gma.addon_type);
+	}
+	if !gma.tags.is_empty() {
+		println!("  tags: {}", gma.tags.join(", "));
+	}
+	println!("  author: {}", gma.author);
+	if !gma.description.is_empty() {
+		println!("  description: {}", gma.description);
+	}
+
+	let width = gma.files.iter().map(|file| file.data.len().to_string().len()).max().unwrap_or(0);
+	for file in &gma.files {
+		println!("{:>width$}  {}", file.data.len(), file.path, width = width);
+	}
+}
+
+/// Writes each file into the output directory, along with an `addon.json` that `gluac pack` can pack them again with.
+fn extract(gma: &Gma, out_dir: &Path) -> Result<(), Box<dyn Error>> {
+	// Don't let a malicious archive write outside of the output directory
+	if let Some(file) = gma.files.iter().find(|file| {
+		!Path::new(&file.path)
+			.components()
+			.all(|component| matches!(component, Component::Normal(_)))
+	}) {
+		return Err(format!("refusing to extract {}, as it isn't a relative path inside the addon", file.path).into());
+	}
+
+	for file in &gma.files {
+		let path = out_dir.join(&file.path);
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent).map_err(PathError::new(parent))?;
+		}
+		std::fs::write(&path, &file.data).map_err(PathError::new(&path))?;
+	}
+
+	let addon_json = serde_json::json!({
+		"title": gma.name,
+		"type": gma.addon_type,
+		"tags": gma.tags,
+		"description": gma.description,
+	});
+	let path = out_dir.join("addon.json");
+	std::fs::write(&path, serde_json::to_string_pretty(&addon_json)?).map_err(PathError::new(&path))?;
+
+	eprintln!("Extracted {} files into {}", gma.files.len(), out_dir.display());
+	Ok(())
+}
+
+pub(crate) fn run(matches: &clap::ArgMatches) -> Result<(), Box<dyn Error>> {
+	let path = PathBuf::from(matches.value_of_os("path").unwrap());
+	let data = std::fs::read(&path).map_err(PathError::new(&path))?;
+	let gma = Gma::read(&data).map_err(|error| format!("{}: {}", path.display(), error))?;
+
+	match matches.value_of_os("output") {
+		Some(out_dir) => extract(&gma, Path::new(out_dir)),
+		None => {
+			list(&gma);
+			Ok(())
+		}
+	}
+}
@@ -0,0 +1,95 @@
+use std::{
+	collections::HashMap,
+	error::Error,
+	path::PathBuf,
+	time::{Duration, SystemTime},
+};
+
+use crate::{
+	build::{self, BuildOptions},
+	report::{self, Format},
+};
+
+pub(crate) fn subcommand() -> clap::App<'static, 'static> {
+	build::source_args(clap::SubCommand::with_name("watch"))
+		.about("Compiles every Lua file in a directory, then recompiles files as they change")
+		.arg(
+			clap::Arg::with_name("interval")
+				.long("interval")
+				.help("How often to check for changes, in milliseconds")
+				.takes_value(true)
+				.default_value("250"),
+		)
+}
+
+/// When each source file was last modified, and its size, to notice when it changes.
+type Snapshot = HashMap<PathBuf, (Option<SystemTime>, u64)>;
+
+fn snapshot(options: &BuildOptions) -> Result<Snapshot, Box<dyn Error>> {
+	let mut snapshot = Snapshot::new();
+	for path in options.sources()? {
+		// The file may have been deleted since it was found, in which case it'll be noticed next time
+		if let Ok(metadata) = std::fs::metadata(options.src_dir.join(&path)) {
+			snapshot.insert(path, (metadata.modified().ok(), metadata.len()));
+		}
+	}
+	Ok(snapshot)
+}
+
+pub(crate) fn run(matches: &clap::ArgMatches) -> Result<(), Box<dyn Error>> {
+	let mut options = BuildOptions::from_matches(matches)?;
+	options.cache = None;
+	let format = Format::from_matches(matches);
+
+	let interval = matches.value_of("interval").unwrap();
+	let interval = Duration::from_millis(interval.parse().map_err(|_| format!("invalid interval: {}", interval))?);
+
+	// Keep one Lua state around rather than creating one for every change
+	let compiler = gluac_rs::compiler()?;
+
+	let mut previous = Snapshot::new();
+	loop {
+		let current = snapshot(&options)?;
+
+		let mut changed = current
+			.iter()
+			.filter(|(path, state)| previous.get(*path) != Some(state))
+			.map(|(path, _)| path)
+			.collect::<Vec<_>>();
+		changed.sort();
+
+		for path in changed {
+			if !previous.contains_key(path) {
+				options.warn_ambiguous_realm(path);
+			}
+
+			let result = options.build_file(&compiler, path);
+			match (format, result) {
+				(Format::Json, result) => report::print_json(
+					Some(&options.src_dir.join(path)),
+					result.as_deref().ok(),
+					result.as_ref().err().map(|error| &**error as &(dyn Error + 'static)),
+				),
+				(Format::Text, Ok(bytecode)) => eprintln!("compiled {} ({} bytes)", build::slash_path(path), bytecode.len()),
+				(Format::Text, Err(error)) => build::report_error(path, &*error),
+			}
+		}
+
+		for path in previous.keys().filter(|path| !current.contains_key(*path)) {
+			let mut removed = false;
+			for out_path in options.out_paths(path) {
+				match std::fs::remove_file(out_path) {
+					Ok(_) => removed = true,
+					Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+					Err(error) => build::report_error(path, &error),
+				}
+			}
+			if removed {
+				eprintln!("removed {}", build::slash_path(path));
+			}
+		}
+
+		previous = current;
+		std::thread::sleep(interval);
+	}
+}
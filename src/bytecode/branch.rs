@@ -0,0 +1,116 @@
+use super::{Header, HeaderFlags, VERSION_2_0, VERSION_2_1};
+
+/// A branch of Garry's Mod, which determines the bytecode format its `lua_shared` can load.
+///
+/// Bytecode can only be loaded by the branch it was compiled for, as the two branches run different versions of LuaJIT.
+///
+/// ```
+/// use gluac_rs::bytecode::{Branch, Header};
+///
+/// // The header of stripped bytecode compiled by the 64-bit branch
+/// let header = Header::read(b"\x1BLJ\x02\x0A").unwrap();
+/// assert_eq!(Branch::of(&header), Some(Branch::X86_64));
+/// assert!(header.check_branch(Branch::X86).is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Branch {
+	/// The 32-bit branch, running LuaJIT 2.0.
+	X86,
+
+	/// The 64-bit branch, running LuaJIT 2.1 with two-slot frames (`LJ_FR2`).
+	X86_64,
+}
+impl Branch {
+	/// The branch whose binaries this crate can load, which depends on the target's pointer width.
+	#[cfg(target_pointer_width = "64")]
+	pub const NATIVE: Branch = Branch::X86_64;
+
+	/// The branch whose binaries this crate can load, which depends on the target's pointer width.
+	#[cfg(target_pointer_width = "32")]
+	pub const NATIVE: Branch = Branch::X86;
+
+	/// The dump version this branch's LuaJIT reads and writes.
+	pub fn version(self) -> u8 {
+		match self {
+			Branch::X86 => VERSION_2_0,
+			Branch::X86_64 => VERSION_2_1,
+		}
+	}
+
+	/// Whether this branch's LuaJIT uses two-slot frames, which changes the slot numbers of calls and iterators.
+	pub fn fr2(self) -> bool {
+		match self {
+			Branch::X86 => false,
+			Branch::X86_64 => true,
+		}
+	}
+
+	/// Returns the branch that can load bytecode with this header, if any.
+	pub fn of(header: &Header) -> Option<Branch> {
+		[Branch::X86, Branch::X86_64]
+			.iter()
+			.copied()
+			.find(|branch| header.check_branch(*branch).is_ok())
+	}
+}
+impl std::fmt::Display for Branch {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Branch::X86 => write!(f, "32-bit branch"),
+			Branch::X86_64 => write!(f, "64-bit branch"),
+		}
+	}
+}
+
+/// Why bytecode can't be loaded by a branch of Garry's Mod, see [`Header::check_branch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Incompatibility {
+	/// The bytecode was dumped by a different version of LuaJIT.
+	Version { branch: Branch, version: u8 },
+
+	/// The bytecode was dumped by a LuaJIT with a different frame layout (`LJ_FR2`) than the branch uses.
+	FrameLayout { branch: Branch, fr2: bool },
+
+	/// The bytecode is big-endian, but every branch is little-endian.
+	BigEndian,
+}
+impl std::fmt::Display for Incompatibility {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Incompatibility::Version { branch, version } => write!(
+				f,
+				"bytecode version {} can't be loaded by the {}, which expects version {}",
+				version,
+				branch,
+				branch.version()
+			),
+			Incompatibility::FrameLayout { branch, fr2: true } => {
+				write!(f, "bytecode uses two-slot frames (FR2), which the {} doesn't", branch)
+			}
+			Incompatibility::FrameLayout { branch, fr2: false } => {
+				write!(f, "bytecode doesn't use two-slot frames (FR2), which the {} requires", branch)
+			}
+			Incompatibility::BigEndian => write!(f, "bytecode is big-endian"),
+		}
+	}
+}
+impl std::error::Error for Incompatibility {}
+
+impl Header {
+	/// Checks whether bytecode with this header can be loaded by the given branch of Garry's Mod.
+	pub fn check_branch(&self, branch: Branch) -> Result<(), Incompatibility> {
+		if self.version != branch.version() {
+			return Err(Incompatibility::Version {
+				branch,
+				version: self.version,
+			});
+		}
+		if self.flags.contains(HeaderFlags::BIG_ENDIAN) {
+			return Err(Incompatibility::BigEndian);
+		}
+		if self.is_fr2() != branch.fr2() {
+			return Err(Incompatibility::FrameLayout { branch, fr2: self.is_fr2() });
+		}
+		Ok(())
+	}
+}
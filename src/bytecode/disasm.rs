@@ -0,0 +1,228 @@
+use std::fmt::Write;
+
+use super::*;
+
+/// Bias added to the number constant of `TSETM` instructions.
+const TSETM_BIAS: f64 = 4503599627370496.0; // 2^52
+
+/// Maximum length of string constants shown in comments, longer strings are truncated and suffixed with `~`
+const MAX_STRING_LEN: usize = 40;
+
+impl BytecodeDump {
+	/// Disassembles every prototype in this dump into a listing similar to `luajit -bl`.
+	///
+	/// Child prototypes are listed before their parents, and each instruction is annotated with its source line if debug information is present.
+	pub fn disassemble(&self) -> String {
+		let chunk_name = match &self.header.chunk_name {
+			Some(chunk_name) => {
+				let chunk_name = chunk_name
+					.strip_prefix(b"@")
+					.or_else(|| chunk_name.strip_prefix(b"="))
+					.unwrap_or(chunk_name);
+				String::from_utf8_lossy(chunk_name).into_owned()
+			}
+			None => "?".to_string(),
+		};
+
+		let mut disassembler = Disassembler {
+			out: String::new(),
+			version: self.header.version,
+			chunk_name,
+		};
+		disassembler.proto(&self.main);
+		disassembler.out
+	}
+}
+
+/// Parses and disassembles a bytecode dump. See [`BytecodeDump::disassemble`].
+pub fn disassemble(bytecode: &[u8]) -> Result<String, ParseError> {
+	Ok(BytecodeDump::parse(bytecode)?.disassemble())
+}
+
+struct Disassembler {
+	out: String,
+	version: u8,
+	chunk_name: String,
+}
+impl Disassembler {
+	fn location(&self, proto: &Prototype) -> String {
+		format!("{}:{}", self.chunk_name, proto.debug.as_ref().map(|debug| debug.first_line).unwrap_or(0))
+	}
+
+	fn proto(&mut self, proto: &Prototype) {
+		for child in proto.children() {
+			self.proto(child);
+		}
+
+		let last_line = proto
+			.debug
+			.as_ref()
+			.map(|debug| debug.first_line.saturating_add(debug.num_lines))
+			.unwrap_or(0);
+		writeln!(self.out, "-- BYTECODE -- {}-{}", self.location(proto), last_line).unwrap();
+
+		let targets = proto
+			.instructions
+			.iter()
+			.enumerate()
+			.filter(|(_, instruction)| instruction.opcode(self.version).map(Opcode::is_jump).unwrap_or(false))
+			.map(|(pc, instruction)| pc as i64 + 1 + instruction.jump_offset() as i64)
+			.collect::<std::collections::HashSet<_>>();
+
+		for (pc, instruction) in proto.instructions.iter().enumerate() {
+			let line = self.instruction(proto, pc, *instruction, targets.contains(&(pc as i64)));
+			self.out.push_str(line.trim_end());
+			self.out.push('\n');
+		}
+
+		self.out.push('\n');
+	}
+
+	fn instruction(&self, proto: &Prototype, pc: usize, instruction: Instruction, is_target: bool) -> String {
+		// Instructions are numbered from 1, as the implicit FUNCF/FUNCV header instruction is 0
+		let mut line = format!("{:04} ", pc + 1);
+
+		if let Some(debug) = &proto.debug {
+			let source_line = debug.lines.get(pc).copied().unwrap_or(0);
+			write!(line, "[{:>4}] ", source_line).unwrap();
+		}
+
+		line.push_str(if is_target { "=> " } else { "   " });
+
+		let opcode = match instruction.opcode(self.version) {
+			Some(opcode) => opcode,
+			None => {
+				write!(line, "???    {:08x}", instruction.0).unwrap();
+				return line;
+			}
+		};
+
+		let (ma, mb, mc) = opcode.modes();
+		if ma == OperandMode::None {
+			write!(line, "{:<6} {:>3} ", opcode.name(), "").unwrap();
+		} else {
+			write!(line, "{:<6} {:>3} ", opcode.name(), instruction.a()).unwrap();
+		}
+
+		if mc == OperandMode::Jump {
+			write!(line, "=> {:04}", pc as i64 + 2 + instruction.jump_offset() as i64).unwrap();
+			return line;
+		}
+
+		let mut d = instruction.d() as i32;
+		if mb != OperandMode::None {
+			d = instruction.c() as i32;
+		} else if mc == OperandMode::None {
+			return line;
+		}
+
+		let mut comment = match mc {
+			OperandMode::Str => Some(match proto.gc_constant(d as u16) {
+				Some(GcConstant::String(string)) => format_string(string),
+				_ => "?".to_string(),
+			}),
+			OperandMode::Num => Some(match proto.num_constant(d as u16) {
+				Some(NumConstant::Int(int)) if opcode == Opcode::TSetM => format_number(*int as f64 - TSETM_BIAS),
+				Some(NumConstant::Num(num)) if opcode == Opcode::TSetM => format_number(num - TSETM_BIAS),
+				Some(NumConstant::Int(int)) => int.to_string(),
+				Some(NumConstant::Num(num)) => format_number(*num),
+				None => "?".to_string(),
+			}),
+			OperandMode::Func => Some(match proto.gc_constant(d as u16) {
+				Some(GcConstant::Child(child)) => self.location(child),
+				_ => "?".to_string(),
+			}),
+			OperandMode::Upvalue => upvalue_name(proto, d),
+			_ => None,
+		};
+
+		if ma == OperandMode::Upvalue {
+			let upvalue = upvalue_name(proto, instruction.a() as i32).unwrap_or_default();
+			comment = Some(match comment {
+				Some(comment) => format!("{} ; {}", upvalue, comment),
+				None => upvalue,
+			});
+		}
+
+		if mb != OperandMode::None {
+			write!(line, "{:>3} {:>3}", instruction.b(), d).unwrap();
+			if let Some(comment) = comment {
+				write!(line, "  ; {}", comment).unwrap();
+			}
+		} else if let Some(comment) = comment {
+			write!(line, "{:>3}      ; {}", d, comment).unwrap();
+		} else {
+			if mc == OperandMode::LitSigned && d > 32767 {
+				d -= 65536;
+			}
+			write!(line, "{:>3}", d).unwrap();
+		}
+
+		line
+	}
+}
+
+fn upvalue_name(proto: &Prototype, index: i32) -> Option<String> {
+	let debug = proto.debug.as_ref()?;
+	let name = debug.upvalue_names.get(index as usize)?;
+	Some(String::from_utf8_lossy(name).into_owned())
+}
+
+/// Quotes a string constant, escaping control characters and truncating it if it's too long.
+fn format_string(string: &[u8]) -> String {
+	let truncated = string.len() > MAX_STRING_LEN;
+	let string = String::from_utf8_lossy(&string[..string.len().min(MAX_STRING_LEN)]);
+
+	let mut formatted = String::with_capacity(string.len() + 3);
+	formatted.push('"');
+	for char in string.chars() {
+		match char {
+			'\n' => formatted.push_str("\\n"),
+			'\r' => formatted.push_str("\\r"),
+			'\t' => formatted.push_str("\\t"),
+			char if char.is_ascii_control() => write!(formatted, "\\{:03}", char as u32).unwrap(),
+			char => formatted.push(char),
+		}
+	}
+	formatted.push('"');
+
+	if truncated {
+		formatted.push('~');
+	}
+
+	formatted
+}
+
+/// Formats a number the same way Lua's `tostring` does (`%.14g`).
+pub(super) fn format_number(num: f64) -> String {
+	if num.is_nan() {
+		return "nan".to_string();
+	}
+	if num.is_infinite() {
+		return if num > 0.0 { "inf" } else { "-inf" }.to_string();
+	}
+	if num == 0.0 {
+		return if num.is_sign_negative() { "-0" } else { "0" }.to_string();
+	}
+
+	// Round to 14 significant digits first, as that can change the exponent
+	let scientific = format!("{:.13e}", num);
+	let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap());
+	let exponent: i32 = exponent[1..].parse().unwrap();
+
+	if (-4..14).contains(&exponent) {
+		let decimals = (13 - exponent) as usize;
+		trim_fraction(format!("{:.*}", decimals, num))
+	} else {
+		let sign = if exponent < 0 { '-' } else { '+' };
+		format!("{}e{}{:02}", trim_fraction(mantissa.to_string()), sign, exponent.abs())
+	}
+}
+
+fn trim_fraction(mut num: String) -> String {
+	if num.contains('.') {
+		let trimmed = num.trim_end_matches('0').trim_end_matches('.').len();
+		num.truncate(trimmed);
+	}
+	num
+}
@@ -0,0 +1,497 @@
+//! Parsing of LuaJIT bytecode dumps, as produced by `string.dump` and this crate's `BytecodeCompiler`.
+//!
+//! ```no_run
+//! let compiler = gluac_rs::compiler().unwrap();
+//! let bytecode = compiler.compile_str("print('Hello, world!')", false).unwrap();
+//!
+//! let dump = gluac_rs::bytecode::parse(&bytecode).unwrap();
+//! println!("{:#?}", dump.main.gc_constants);
+//! ```
+
+mod opcode;
+pub use opcode::*;
+
+mod read;
+mod write;
+
+mod disasm;
+pub use disasm::disassemble;
+
+mod branch;
+pub use branch::{Branch, Incompatibility};
+
+mod translate;
+pub use translate::{translate, TranslateError};
+
+/// Dump version produced by LuaJIT 2.0, used by the 32-bit branch of Garry's Mod.
+pub const VERSION_2_0: u8 = 1;
+
+/// Dump version produced by LuaJIT 2.1, used by the 64-bit branch of Garry's Mod.
+pub const VERSION_2_1: u8 = 2;
+
+/// The first bytes of every bytecode dump (`ESC 'L' 'J'`)
+pub const SIGNATURE: &[u8; 3] = b"\x1BLJ";
+
+/// A parsed LuaJIT bytecode dump.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BytecodeDump {
+	pub header: Header,
+
+	/// The prototype of the main chunk. Nested functions are found in its [`GcConstant::Child`] constants.
+	pub main: Prototype,
+}
+impl BytecodeDump {
+	/// Parses a bytecode dump.
+	pub fn parse(bytecode: &[u8]) -> Result<BytecodeDump, ParseError> {
+		read::Reader::new(bytecode).read_dump()
+	}
+
+	/// Serializes this dump back into bytecode.
+	///
+	/// Writing a parsed dump produces the exact bytes it was parsed from, so this can be used to emit bytecode after editing the parsed structures.
+	///
+	/// Debug information is only written if the header doesn't have the [`HeaderFlags::STRIP`] flag set, and the chunk name is omitted likewise.
+	pub fn write(&self) -> Result<crate::Bytecode, WriteError> {
+		write::Writer::new(&self.header).write_dump(self)
+	}
+
+	/// Removes the chunk name and the debug information (line numbers, upvalue names and variable names) of every prototype.
+	///
+	/// Writing a stripped dump produces the same bytecode as `string.dump(f, true)`.
+	pub fn strip(&mut self) {
+		self.header.flags.set(HeaderFlags::STRIP, true);
+		self.header.chunk_name = None;
+		self.main.strip();
+	}
+}
+
+/// Parses a bytecode dump.
+pub fn parse(bytecode: &[u8]) -> Result<BytecodeDump, ParseError> {
+	BytecodeDump::parse(bytecode)
+}
+
+/// Reads just the header of a bytecode dump, without parsing the rest of it. See [`Header::read`].
+pub fn header(bytecode: &[u8]) -> Result<Header, ParseError> {
+	Header::read(bytecode)
+}
+
+/// Strips the debug information from already compiled bytecode, without needing to recompile it.
+///
+/// The output is identical to the bytecode produced by compiling the same source with `strip_debug` enabled. See [`BytecodeDump::strip`].
+pub fn strip(bytecode: &[u8]) -> Result<crate::Bytecode, ParseError> {
+	let mut dump = BytecodeDump::parse(bytecode)?;
+	dump.strip();
+	Ok(dump
+		.write()
+		.expect("Stripped bytecode that was parsed successfully should always be writable"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+	/// [`VERSION_2_0`] or [`VERSION_2_1`]
+	pub version: u8,
+	pub flags: HeaderFlags,
+
+	/// The chunk name the source was loaded with. Not present in stripped bytecode.
+	pub chunk_name: Option<Vec<u8>>,
+}
+impl Header {
+	/// Reads just the header of a bytecode dump, which is much faster than parsing all of it.
+	///
+	/// Use [`check_branch`](Header::check_branch) to check whether the bytecode can be loaded by a branch of Garry's Mod.
+	pub fn read(bytecode: &[u8]) -> Result<Header, ParseError> {
+		read::Reader::new(bytecode).read_header()
+	}
+
+	/// Whether debug information has been stripped.
+	#[inline]
+	pub fn is_stripped(&self) -> bool {
+		self.flags.contains(HeaderFlags::STRIP)
+	}
+
+	/// Whether the bytecode contains FFI constants (cdata).
+	#[inline]
+	pub fn has_ffi(&self) -> bool {
+		self.flags.contains(HeaderFlags::FFI)
+	}
+
+	/// Whether numbers and instructions are stored in big-endian byte order.
+	#[inline]
+	pub fn is_big_endian(&self) -> bool {
+		self.flags.contains(HeaderFlags::BIG_ENDIAN)
+	}
+
+	/// Whether the bytecode uses two-slot frames (`LJ_FR2`), as LuaJIT 2.1 does with 64-bit GC references.
+	#[inline]
+	pub fn is_fr2(&self) -> bool {
+		self.flags.contains(HeaderFlags::FR2)
+	}
+}
+
+/// `BCDUMP_F_*` flags from the header of a bytecode dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct HeaderFlags(pub u32);
+impl HeaderFlags {
+	/// Numbers and instructions are stored in big-endian byte order.
+	pub const BIG_ENDIAN: HeaderFlags = HeaderFlags(0x01);
+
+	/// Debug information has been stripped.
+	pub const STRIP: HeaderFlags = HeaderFlags(0x02);
+
+	/// The bytecode contains FFI constants (cdata).
+	pub const FFI: HeaderFlags = HeaderFlags(0x04);
+
+	/// The bytecode uses two-slot frame info (LuaJIT 2.1 with `LJ_FR2`, i.e. 64-bit GC references).
+	pub const FR2: HeaderFlags = HeaderFlags(0x08);
+
+	#[inline]
+	pub fn contains(self, flags: HeaderFlags) -> bool {
+		self.0 & flags.0 == flags.0
+	}
+
+	#[inline]
+	pub fn set(&mut self, flags: HeaderFlags, enabled: bool) {
+		if enabled {
+			self.0 |= flags.0;
+		} else {
+			self.0 &= !flags.0;
+		}
+	}
+}
+
+/// `PROTO_*` flags of a function prototype.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ProtoFlags(pub u8);
+impl ProtoFlags {
+	/// The prototype has child prototypes (nested functions).
+	pub const CHILD: ProtoFlags = ProtoFlags(0x01);
+
+	/// The function is variadic.
+	pub const VARARG: ProtoFlags = ProtoFlags(0x02);
+
+	/// The prototype uses FFI constants (cdata).
+	pub const FFI: ProtoFlags = ProtoFlags(0x04);
+
+	#[inline]
+	pub fn contains(self, flags: ProtoFlags) -> bool {
+		self.0 & flags.0 == flags.0
+	}
+}
+
+/// A function prototype.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prototype {
+	pub flags: ProtoFlags,
+	pub num_params: u8,
+
+	/// The number of stack slots the function needs.
+	pub frame_size: u8,
+
+	/// The function's instructions, not including the implicit `FUNCF`/`FUNCV` header instruction.
+	pub instructions: Vec<Instruction>,
+
+	/// References to the upvalues of the function, see [`Upvalue`].
+	pub upvalues: Vec<Upvalue>,
+
+	/// GC constants, indexed by the D operand of the instructions that reference them.
+	///
+	/// Note that in the bytecode dump these are stored in reverse order.
+	pub gc_constants: Vec<GcConstant>,
+
+	/// Number constants, indexed by the D operand of the instructions that reference them.
+	pub num_constants: Vec<NumConstant>,
+
+	/// Line numbers, upvalue names and variable names. Not present in stripped bytecode.
+	pub debug: Option<DebugInfo>,
+}
+impl Prototype {
+	/// Returns the GC constant referenced by the D operand of an instruction.
+	#[inline]
+	pub fn gc_constant(&self, d: u16) -> Option<&GcConstant> {
+		self.gc_constants.get(d as usize)
+	}
+
+	/// Returns the number constant referenced by an instruction operand.
+	#[inline]
+	pub fn num_constant(&self, d: u16) -> Option<&NumConstant> {
+		self.num_constants.get(d as usize)
+	}
+
+	/// Removes the debug information of this prototype and its children.
+	pub fn strip(&mut self) {
+		self.debug = None;
+		for constant in &mut self.gc_constants {
+			if let GcConstant::Child(child) = constant {
+				child.strip();
+			}
+		}
+	}
+
+	/// Returns the child prototypes (nested functions) of this prototype, in order of their constant index.
+	pub fn children(&self) -> impl Iterator<Item = &Prototype> {
+		self.gc_constants.iter().filter_map(|constant| match constant {
+			GcConstant::Child(child) => Some(child),
+			_ => None,
+		})
+	}
+}
+
+/// An upvalue reference, stored as a `u16` in the bytecode dump.
+///
+/// If [`is_local`](Upvalue::is_local), the upvalue refers to a stack slot of the enclosing function, otherwise it refers to one of the enclosing function's upvalues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Upvalue(pub u16);
+impl Upvalue {
+	const LOCAL: u16 = 0x8000;
+	const IMMUTABLE: u16 = 0x4000;
+
+	#[inline]
+	pub fn is_local(self) -> bool {
+		self.0 & Self::LOCAL != 0
+	}
+
+	#[inline]
+	pub fn is_immutable(self) -> bool {
+		self.0 & Self::IMMUTABLE != 0
+	}
+
+	/// The slot or upvalue index this upvalue refers to.
+	#[inline]
+	pub fn index(self) -> u16 {
+		self.0 & !(Self::LOCAL | Self::IMMUTABLE)
+	}
+}
+
+/// A GC (garbage collected) constant of a prototype.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GcConstant {
+	/// A nested function.
+	Child(Prototype),
+	/// A template table, used by `TDUP`.
+	Table(TableConstant),
+	/// A signed 64-bit integer cdata (FFI)
+	I64(i64),
+	/// An unsigned 64-bit integer cdata (FFI)
+	U64(u64),
+	/// A complex number cdata (FFI)
+	Complex(f64, f64),
+	String(Vec<u8>),
+}
+
+/// A template table constant.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TableConstant {
+	pub array: Vec<TableValue>,
+	pub hash: Vec<(TableValue, TableValue)>,
+}
+
+/// A key or value of a template table constant.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableValue {
+	Nil,
+	False,
+	True,
+	Int(i32),
+	Num(f64),
+	String(Vec<u8>),
+}
+
+/// A number constant of a prototype.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NumConstant {
+	Int(i32),
+	Num(f64),
+}
+
+/// Debug information of a prototype.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugInfo {
+	/// The line the function was defined on.
+	pub first_line: u32,
+
+	/// The number of lines the function spans, after `first_line`.
+	pub num_lines: u32,
+
+	/// The source line of each instruction.
+	pub lines: Vec<u32>,
+
+	/// The names of the prototype's upvalues.
+	pub upvalue_names: Vec<Vec<u8>>,
+
+	/// The prototype's local variables, in order of declaration.
+	pub variables: Vec<VarInfo>,
+}
+
+/// A local variable and the range of instructions it is in scope for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarInfo {
+	pub name: VarName,
+	pub start_pc: u32,
+	pub end_pc: u32,
+}
+
+/// The name of a local variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VarName {
+	Named(Vec<u8>),
+
+	/// The hidden index variable of a numeric `for` loop.
+	ForIndex,
+	/// The hidden limit variable of a numeric `for` loop.
+	ForStop,
+	/// The hidden step variable of a numeric `for` loop.
+	ForStep,
+	/// The hidden generator variable of a generic `for` loop.
+	ForGenerator,
+	/// The hidden state variable of a generic `for` loop.
+	ForState,
+	/// The hidden control variable of a generic `for` loop.
+	ForControl,
+}
+impl VarName {
+	/// The name LuaJIT uses for hidden variables, e.g. in `luajit -bl` output.
+	pub fn as_bytes(&self) -> &[u8] {
+		match self {
+			VarName::Named(name) => name,
+			VarName::ForIndex => b"(for index)",
+			VarName::ForStop => b"(for limit)",
+			VarName::ForStep => b"(for step)",
+			VarName::ForGenerator => b"(for generator)",
+			VarName::ForState => b"(for state)",
+			VarName::ForControl => b"(for control)",
+		}
+	}
+}
+
+/// A 32-bit bytecode instruction.
+///
+/// Instructions have an 8-bit opcode and an 8-bit A operand, followed by either 8-bit C and B operands or a single 16-bit D operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Instruction(pub u32);
+impl Instruction {
+	#[inline]
+	pub fn new_abc(op: u8, a: u8, b: u8, c: u8) -> Instruction {
+		Instruction(op as u32 | (a as u32) << 8 | (c as u32) << 16 | (b as u32) << 24)
+	}
+
+	#[inline]
+	pub fn new_ad(op: u8, a: u8, d: u16) -> Instruction {
+		Instruction(op as u32 | (a as u32) << 8 | (d as u32) << 16)
+	}
+
+	/// The opcode number of this instruction. Use [`opcode`](Instruction::opcode) to decode it.
+	#[inline]
+	pub fn op(self) -> u8 {
+		self.0 as u8
+	}
+
+	/// Decodes the opcode of this instruction for the given dump version.
+	#[inline]
+	pub fn opcode(self, version: u8) -> Option<Opcode> {
+		Opcode::from_u8(self.op(), version)
+	}
+
+	#[inline]
+	pub fn a(self) -> u8 {
+		(self.0 >> 8) as u8
+	}
+
+	#[inline]
+	pub fn b(self) -> u8 {
+		(self.0 >> 24) as u8
+	}
+
+	#[inline]
+	pub fn c(self) -> u8 {
+		(self.0 >> 16) as u8
+	}
+
+	#[inline]
+	pub fn d(self) -> u16 {
+		(self.0 >> 16) as u16
+	}
+
+	/// The D operand as a branch offset relative to the next instruction.
+	#[inline]
+	pub fn jump_offset(self) -> i32 {
+		self.d() as i32 - 0x8000
+	}
+}
+
+/// An error encountered while parsing a bytecode dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+	/// The data doesn't start with `ESC 'L' 'J'`
+	InvalidSignature,
+
+	/// The dump version isn't one we know how to read.
+	UnsupportedVersion(u8),
+
+	/// The header has flags set that aren't valid for its dump version.
+	UnknownFlags(u32),
+
+	/// The data ended in the middle of the dump.
+	UnexpectedEof,
+
+	/// A ULEB128 number doesn't fit in 32 bits.
+	InvalidUleb128 { offset: usize },
+
+	/// A constant has an unknown type tag.
+	InvalidConstant { offset: usize, tag: u32 },
+
+	/// A prototype refers to a child prototype that doesn't exist.
+	MissingChild { offset: usize },
+
+	/// A prototype's length doesn't match its contents.
+	InvalidPrototypeLength { offset: usize },
+
+	/// A prototype's debug information is malformed.
+	InvalidDebugInfo { offset: usize },
+
+	/// There wasn't exactly one prototype (the main chunk) left after reading every prototype.
+	InvalidPrototypeCount(usize),
+
+	/// There is data after the end of the dump.
+	TrailingData { offset: usize },
+}
+impl std::fmt::Display for ParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ParseError::InvalidSignature => write!(f, "not a LuaJIT bytecode dump"),
+			ParseError::UnsupportedVersion(version) => write!(f, "unsupported bytecode dump version {}", version),
+			ParseError::UnknownFlags(flags) => write!(f, "unknown bytecode dump flags {:#x}", flags),
+			ParseError::UnexpectedEof => write!(f, "unexpected end of bytecode dump"),
+			ParseError::InvalidUleb128 { offset } => write!(f, "invalid ULEB128 number at offset {}", offset),
+			ParseError::InvalidConstant { offset, tag } => write!(f, "invalid constant type {} at offset {}", tag, offset),
+			ParseError::MissingChild { offset } => write!(f, "missing child prototype for constant at offset {}", offset),
+			ParseError::InvalidPrototypeLength { offset } => write!(f, "prototype at offset {} doesn't match its length", offset),
+			ParseError::InvalidDebugInfo { offset } => write!(f, "invalid debug info in prototype at offset {}", offset),
+			ParseError::InvalidPrototypeCount(count) => write!(f, "expected 1 main prototype, found {}", count),
+			ParseError::TrailingData { offset } => write!(f, "trailing data at offset {}", offset),
+		}
+	}
+}
+impl std::error::Error for ParseError {}
+
+/// An error encountered while serializing a bytecode dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteError {
+	/// A prototype has more than 255 upvalues.
+	TooManyUpvalues(usize),
+
+	/// A prototype's debug information doesn't match its instructions or upvalues, or contains names that can't be represented.
+	InvalidDebugInfo,
+
+	/// A string, constant list or prototype is too large to be represented in a bytecode dump.
+	TooLarge,
+}
+impl std::fmt::Display for WriteError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			WriteError::TooManyUpvalues(count) => write!(f, "prototype has {} upvalues, but at most 255 are allowed", count),
+			WriteError::InvalidDebugInfo => write!(f, "debug info doesn't match its prototype"),
+			WriteError::TooLarge => write!(f, "bytecode dump is too large"),
+		}
+	}
+}
+impl std::error::Error for WriteError {}
@@ -0,0 +1,214 @@
+/// The kind of value an instruction operand refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OperandMode {
+	/// The operand is unused.
+	None,
+	/// Destination slot.
+	Dst,
+	/// Base slot of a range of slots.
+	Base,
+	/// Variable slot.
+	Var,
+	/// Read-only base slot.
+	RBase,
+	/// Upvalue index.
+	Upvalue,
+	/// Unsigned literal.
+	Lit,
+	/// Signed literal.
+	LitSigned,
+	/// Primitive type (`nil`, `false` or `true`).
+	Pri,
+	/// Number constant index.
+	Num,
+	/// String constant index.
+	Str,
+	/// Template table constant index.
+	Tab,
+	/// Function prototype constant index.
+	Func,
+	/// Branch target, relative to the next instruction.
+	Jump,
+	/// cdata constant index.
+	CData,
+}
+
+macro_rules! opcodes {
+	( $( $variant:ident $name:literal $a:ident $b:ident $cd:ident, )* ) => {
+		/// A LuaJIT bytecode instruction opcode.
+		///
+		/// The discriminants are the opcode numbers used by LuaJIT 2.1. Use [`Opcode::from_u8`] and [`Opcode::to_u8`] to convert to and from the opcode numbers of a specific bytecode version.
+		#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+		#[repr(u8)]
+		pub enum Opcode {
+			$( $variant, )*
+		}
+		impl Opcode {
+			/// Every opcode, indexed by its LuaJIT 2.1 opcode number.
+			pub const ALL: &'static [Opcode] = &[$( Opcode::$variant, )*];
+
+			/// The name LuaJIT uses for this opcode, e.g. `"GGET"`.
+			pub fn name(self) -> &'static str {
+				match self {
+					$( Opcode::$variant => $name, )*
+				}
+			}
+
+			/// The operand modes of the A, B and C/D operands of this opcode.
+			pub fn modes(self) -> (OperandMode, OperandMode, OperandMode) {
+				match self {
+					$( Opcode::$variant => (OperandMode::$a, OperandMode::$b, OperandMode::$cd), )*
+				}
+			}
+		}
+	};
+}
+
+opcodes! {
+	IsLt "ISLT" Var None Var,
+	IsGe "ISGE" Var None Var,
+	IsLe "ISLE" Var None Var,
+	IsGt "ISGT" Var None Var,
+	IsEqV "ISEQV" Var None Var,
+	IsNeV "ISNEV" Var None Var,
+	IsEqS "ISEQS" Var None Str,
+	IsNeS "ISNES" Var None Str,
+	IsEqN "ISEQN" Var None Num,
+	IsNeN "ISNEN" Var None Num,
+	IsEqP "ISEQP" Var None Pri,
+	IsNeP "ISNEP" Var None Pri,
+	IsTc "ISTC" Dst None Var,
+	IsFc "ISFC" Dst None Var,
+	IsT "IST" None None Var,
+	IsF "ISF" None None Var,
+	IsType "ISTYPE" Var None Lit,
+	IsNum "ISNUM" Var None Lit,
+	Mov "MOV" Dst None Var,
+	Not "NOT" Dst None Var,
+	Unm "UNM" Dst None Var,
+	Len "LEN" Dst None Var,
+	AddVN "ADDVN" Dst Var Num,
+	SubVN "SUBVN" Dst Var Num,
+	MulVN "MULVN" Dst Var Num,
+	DivVN "DIVVN" Dst Var Num,
+	ModVN "MODVN" Dst Var Num,
+	AddNV "ADDNV" Dst Var Num,
+	SubNV "SUBNV" Dst Var Num,
+	MulNV "MULNV" Dst Var Num,
+	DivNV "DIVNV" Dst Var Num,
+	ModNV "MODNV" Dst Var Num,
+	AddVV "ADDVV" Dst Var Var,
+	SubVV "SUBVV" Dst Var Var,
+	MulVV "MULVV" Dst Var Var,
+	DivVV "DIVVV" Dst Var Var,
+	ModVV "MODVV" Dst Var Var,
+	Pow "POW" Dst Var Var,
+	Cat "CAT" Dst RBase RBase,
+	KStr "KSTR" Dst None Str,
+	KCData "KCDATA" Dst None CData,
+	KShort "KSHORT" Dst None LitSigned,
+	KNum "KNUM" Dst None Num,
+	KPri "KPRI" Dst None Pri,
+	KNil "KNIL" Base None Base,
+	UGet "UGET" Dst None Upvalue,
+	USetV "USETV" Upvalue None Var,
+	USetS "USETS" Upvalue None Str,
+	USetN "USETN" Upvalue None Num,
+	USetP "USETP" Upvalue None Pri,
+	UClo "UCLO" RBase None Jump,
+	FNew "FNEW" Dst None Func,
+	TNew "TNEW" Dst None Lit,
+	TDup "TDUP" Dst None Tab,
+	GGet "GGET" Dst None Str,
+	GSet "GSET" Var None Str,
+	TGetV "TGETV" Dst Var Var,
+	TGetS "TGETS" Dst Var Str,
+	TGetB "TGETB" Dst Var Lit,
+	TGetR "TGETR" Dst Var Var,
+	TSetV "TSETV" Var Var Var,
+	TSetS "TSETS" Var Var Str,
+	TSetB "TSETB" Var Var Lit,
+	TSetM "TSETM" Base None Num,
+	TSetR "TSETR" Var Var Var,
+	CallM "CALLM" Base Lit Lit,
+	Call "CALL" Base Lit Lit,
+	CallMT "CALLMT" Base None Lit,
+	CallT "CALLT" Base None Lit,
+	IterC "ITERC" Base Lit Lit,
+	IterN "ITERN" Base Lit Lit,
+	VArg "VARG" Base Lit Lit,
+	IsNext "ISNEXT" Base None Jump,
+	RetM "RETM" Base None Lit,
+	Ret "RET" RBase None Lit,
+	Ret0 "RET0" RBase None Lit,
+	Ret1 "RET1" RBase None Lit,
+	ForI "FORI" Base None Jump,
+	JForI "JFORI" Base None Jump,
+	ForL "FORL" Base None Jump,
+	IForL "IFORL" Base None Jump,
+	JForL "JFORL" Base None Lit,
+	IterL "ITERL" Base None Jump,
+	IIterL "IITERL" Base None Jump,
+	JIterL "JITERL" Base None Lit,
+	Loop "LOOP" RBase None Jump,
+	ILoop "ILOOP" RBase None Jump,
+	JLoop "JLOOP" RBase None Lit,
+	Jmp "JMP" RBase None Jump,
+	FuncF "FUNCF" RBase None None,
+	IFuncF "IFUNCF" RBase None None,
+	JFuncF "JFUNCF" RBase None Lit,
+	FuncV "FUNCV" RBase None None,
+	IFuncV "IFUNCV" RBase None None,
+	JFuncV "JFUNCV" RBase None Lit,
+	FuncC "FUNCC" RBase None None,
+	FuncCW "FUNCCW" RBase None None,
+}
+
+impl Opcode {
+	/// Decodes an opcode number from bytecode of the given dump version.
+	///
+	/// Returns `None` if the opcode number doesn't exist in that version.
+	pub fn from_u8(op: u8, version: u8) -> Option<Opcode> {
+		let op = match version {
+			super::VERSION_2_0 => match op {
+				0..=15 => op,
+				16..=56 => op + 2,
+				57..=60 => op + 3,
+				_ => op.checked_add(4)?,
+			},
+			super::VERSION_2_1 => op,
+			_ => return None,
+		};
+		Opcode::ALL.get(op as usize).copied()
+	}
+
+	/// Encodes this opcode as its opcode number in bytecode of the given dump version.
+	///
+	/// Returns `None` if this opcode doesn't exist in that version.
+	pub fn to_u8(self, version: u8) -> Option<u8> {
+		let op = self as u8;
+		match version {
+			super::VERSION_2_0 => match self {
+				Opcode::IsType | Opcode::IsNum | Opcode::TGetR | Opcode::TSetR => None,
+				_ => Some(match op {
+					0..=15 => op,
+					18..=58 => op - 2,
+					60..=63 => op - 3,
+					_ => op - 4,
+				}),
+			},
+			super::VERSION_2_1 => Some(op),
+			_ => None,
+		}
+	}
+
+	/// Whether the D operand of this opcode is a branch target.
+	pub fn is_jump(self) -> bool {
+		self.modes().2 == OperandMode::Jump
+	}
+
+	/// Whether this opcode uses separate B and C operands rather than a single D operand.
+	pub fn has_b(self) -> bool {
+		self.modes().1 != OperandMode::None
+	}
+}
@@ -0,0 +1,385 @@
+use super::*;
+
+/// Type tags of GC constants (`BCDUMP_KGC_*`). Strings are tagged with `KGC_STR + len`.
+pub(super) const KGC_CHILD: u32 = 0;
+pub(super) const KGC_TAB: u32 = 1;
+pub(super) const KGC_I64: u32 = 2;
+pub(super) const KGC_U64: u32 = 3;
+pub(super) const KGC_COMPLEX: u32 = 4;
+pub(super) const KGC_STR: u32 = 5;
+
+/// Type tags of template table keys and values (`BCDUMP_KTAB_*`). Strings are tagged with `KTAB_STR + len`.
+pub(super) const KTAB_NIL: u32 = 0;
+pub(super) const KTAB_FALSE: u32 = 1;
+pub(super) const KTAB_TRUE: u32 = 2;
+pub(super) const KTAB_INT: u32 = 3;
+pub(super) const KTAB_NUM: u32 = 4;
+pub(super) const KTAB_STR: u32 = 5;
+
+/// Names of hidden `for` loop variables are stored as these single bytes (`VARNAME_*`) rather than strings.
+pub(super) const VARNAME_END: u8 = 0;
+pub(super) const VARNAME_FOR_IDX: u8 = 1;
+pub(super) const VARNAME_FOR_STOP: u8 = 2;
+pub(super) const VARNAME_FOR_STEP: u8 = 3;
+pub(super) const VARNAME_FOR_GEN: u8 = 4;
+pub(super) const VARNAME_FOR_STATE: u8 = 5;
+pub(super) const VARNAME_FOR_CTL: u8 = 6;
+
+pub(super) struct Reader<'a> {
+	data: &'a [u8],
+	pos: usize,
+	big_endian: bool,
+	stripped: bool,
+}
+impl<'a> Reader<'a> {
+	pub(super) fn new(data: &'a [u8]) -> Self {
+		Self {
+			data,
+			pos: 0,
+			big_endian: false,
+			stripped: false,
+		}
+	}
+
+	fn bytes(&mut self, len: usize) -> Result<&'a [u8], ParseError> {
+		let end = self.end_of(len)?;
+		let bytes = &self.data[self.pos..end];
+		self.pos = end;
+		Ok(bytes)
+	}
+
+	/// The end of the data, after checking that it's at least `len` bytes after the current position.
+	fn end_of(&self, len: usize) -> Result<usize, ParseError> {
+		self.pos
+			.checked_add(len)
+			.filter(|end| *end <= self.data.len())
+			.ok_or(ParseError::UnexpectedEof)
+	}
+
+	/// The capacity to preallocate for `count` items read from the data, which can't be more than the number of bytes left
+	/// as every item takes at least one byte. This stops malformed counts from allocating huge amounts of memory.
+	fn capacity(&self, count: usize) -> usize {
+		count.min(self.data.len() - self.pos)
+	}
+
+	fn byte(&mut self) -> Result<u8, ParseError> {
+		Ok(self.bytes(1)?[0])
+	}
+
+	fn u16(&mut self) -> Result<u16, ParseError> {
+		let bytes = [self.byte()?, self.byte()?];
+		Ok(if self.big_endian {
+			u16::from_be_bytes(bytes)
+		} else {
+			u16::from_le_bytes(bytes)
+		})
+	}
+
+	fn u32(&mut self) -> Result<u32, ParseError> {
+		let bytes = [self.byte()?, self.byte()?, self.byte()?, self.byte()?];
+		Ok(if self.big_endian {
+			u32::from_be_bytes(bytes)
+		} else {
+			u32::from_le_bytes(bytes)
+		})
+	}
+
+	fn uleb128_wide(&mut self) -> Result<u64, ParseError> {
+		let offset = self.pos;
+		let mut value = 0u64;
+		let mut shift = 0;
+		loop {
+			let byte = self.byte()?;
+			if shift >= 35 {
+				return Err(ParseError::InvalidUleb128 { offset });
+			}
+			value |= ((byte & 0x7F) as u64) << shift;
+			shift += 7;
+			if byte < 0x80 {
+				return Ok(value);
+			}
+		}
+	}
+
+	fn uleb128(&mut self) -> Result<u32, ParseError> {
+		let offset = self.pos;
+		let value = self.uleb128_wide()?;
+		if value > u32::MAX as u64 {
+			return Err(ParseError::InvalidUleb128 { offset });
+		}
+		Ok(value as u32)
+	}
+
+	/// Reads a 33-bit ULEB128 number, returning its lowest bit separately.
+	fn uleb128_33(&mut self) -> Result<(bool, u32), ParseError> {
+		let offset = self.pos;
+		let value = self.uleb128_wide()?;
+		if value >> 33 != 0 {
+			return Err(ParseError::InvalidUleb128 { offset });
+		}
+		Ok((value & 1 != 0, (value >> 1) as u32))
+	}
+
+	fn f64(&mut self) -> Result<f64, ParseError> {
+		let lo = self.uleb128()?;
+		let hi = self.uleb128()?;
+		Ok(f64::from_bits((hi as u64) << 32 | lo as u64))
+	}
+
+	fn u64(&mut self) -> Result<u64, ParseError> {
+		let lo = self.uleb128()?;
+		let hi = self.uleb128()?;
+		Ok((hi as u64) << 32 | lo as u64)
+	}
+
+	pub(super) fn read_dump(mut self) -> Result<BytecodeDump, ParseError> {
+		let header = self.read_header()?;
+
+		let mut protos = Vec::new();
+		loop {
+			let offset = self.pos;
+			let len = self.uleb128()? as usize;
+			if len == 0 {
+				break;
+			}
+
+			let end = self.end_of(len)?;
+			let proto = self.read_proto(&mut protos)?;
+			if self.pos != end {
+				return Err(ParseError::InvalidPrototypeLength { offset });
+			}
+			protos.push(proto);
+		}
+
+		if self.pos != self.data.len() {
+			return Err(ParseError::TrailingData { offset: self.pos });
+		}
+
+		if protos.len() != 1 {
+			return Err(ParseError::InvalidPrototypeCount(protos.len()));
+		}
+
+		Ok(BytecodeDump {
+			header,
+			main: protos.pop().unwrap(),
+		})
+	}
+
+	pub(super) fn read_header(&mut self) -> Result<Header, ParseError> {
+		if !self.data.starts_with(SIGNATURE) {
+			return Err(ParseError::InvalidSignature);
+		}
+		self.pos = SIGNATURE.len();
+
+		let version = self.byte()?;
+		let known_flags = match version {
+			VERSION_2_0 => HeaderFlags::BIG_ENDIAN.0 | HeaderFlags::STRIP.0 | HeaderFlags::FFI.0,
+			VERSION_2_1 => HeaderFlags::BIG_ENDIAN.0 | HeaderFlags::STRIP.0 | HeaderFlags::FFI.0 | HeaderFlags::FR2.0,
+			_ => return Err(ParseError::UnsupportedVersion(version)),
+		};
+
+		let flags = HeaderFlags(self.uleb128()?);
+		if flags.0 & !known_flags != 0 {
+			return Err(ParseError::UnknownFlags(flags.0));
+		}
+
+		self.big_endian = flags.contains(HeaderFlags::BIG_ENDIAN);
+		self.stripped = flags.contains(HeaderFlags::STRIP);
+
+		let chunk_name = if self.stripped {
+			None
+		} else {
+			let len = self.uleb128()? as usize;
+			Some(self.bytes(len)?.to_vec())
+		};
+
+		Ok(Header { version, flags, chunk_name })
+	}
+
+	fn read_proto(&mut self, protos: &mut Vec<Prototype>) -> Result<Prototype, ParseError> {
+		let offset = self.pos;
+
+		let flags = ProtoFlags(self.byte()?);
+		let num_params = self.byte()?;
+		let frame_size = self.byte()?;
+		let num_upvalues = self.byte()? as usize;
+		let num_gc_constants = self.uleb128()? as usize;
+		let num_num_constants = self.uleb128()? as usize;
+		let num_instructions = self.uleb128()? as usize;
+
+		let mut debug_header = None;
+		if !self.stripped {
+			let debug_len = self.uleb128()? as usize;
+			if debug_len != 0 {
+				let first_line = self.uleb128()?;
+				let num_lines = self.uleb128()?;
+				debug_header = Some((debug_len, first_line, num_lines));
+			}
+		}
+
+		let instructions = (0..num_instructions)
+			.map(|_| self.u32().map(Instruction))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let upvalues = (0..num_upvalues).map(|_| self.u16().map(Upvalue)).collect::<Result<Vec<_>, _>>()?;
+
+		let mut gc_constants = Vec::with_capacity(self.capacity(num_gc_constants));
+		for _ in 0..num_gc_constants {
+			gc_constants.push(self.read_gc_constant(protos)?);
+		}
+		gc_constants.reverse();
+
+		let mut num_constants = Vec::with_capacity(self.capacity(num_num_constants));
+		for _ in 0..num_num_constants {
+			num_constants.push(match self.uleb128_33()? {
+				(true, lo) => {
+					let hi = self.uleb128()?;
+					NumConstant::Num(f64::from_bits((hi as u64) << 32 | lo as u64))
+				}
+				(false, int) => NumConstant::Int(int as i32),
+			});
+		}
+
+		let debug = match debug_header {
+			Some((debug_len, first_line, num_lines)) => {
+				let end = self.end_of(debug_len).map_err(|_| ParseError::InvalidDebugInfo { offset })?;
+
+				// Don't let the debug info be read past its end
+				let data = self.data;
+				self.data = &data[..end];
+				let debug = self.read_debug_info(first_line, num_lines, num_instructions, num_upvalues, end);
+				self.data = data;
+
+				match debug {
+					Ok(debug) if self.pos == end => Some(debug),
+					_ => return Err(ParseError::InvalidDebugInfo { offset }),
+				}
+			}
+			None => None,
+		};
+
+		Ok(Prototype {
+			flags,
+			num_params,
+			frame_size,
+			instructions,
+			upvalues,
+			gc_constants,
+			num_constants,
+			debug,
+		})
+	}
+
+	fn read_gc_constant(&mut self, protos: &mut Vec<Prototype>) -> Result<GcConstant, ParseError> {
+		let offset = self.pos;
+		Ok(match self.uleb128()? {
+			KGC_CHILD => GcConstant::Child(protos.pop().ok_or(ParseError::MissingChild { offset })?),
+			KGC_TAB => GcConstant::Table(self.read_table()?),
+			KGC_I64 => GcConstant::I64(self.u64()? as i64),
+			KGC_U64 => GcConstant::U64(self.u64()?),
+			KGC_COMPLEX => GcConstant::Complex(self.f64()?, self.f64()?),
+			tag => GcConstant::String(self.bytes((tag - KGC_STR) as usize)?.to_vec()),
+		})
+	}
+
+	fn read_table(&mut self) -> Result<TableConstant, ParseError> {
+		let array_len = self.uleb128()? as usize;
+		let hash_len = self.uleb128()? as usize;
+
+		let mut table = TableConstant {
+			array: Vec::with_capacity(self.capacity(array_len)),
+			hash: Vec::with_capacity(self.capacity(hash_len)),
+		};
+		for _ in 0..array_len {
+			table.array.push(self.read_table_value()?);
+		}
+		for _ in 0..hash_len {
+			table.hash.push((self.read_table_value()?, self.read_table_value()?));
+		}
+		Ok(table)
+	}
+
+	fn read_table_value(&mut self) -> Result<TableValue, ParseError> {
+		let offset = self.pos;
+		Ok(match self.uleb128()? {
+			KTAB_NIL => TableValue::Nil,
+			KTAB_FALSE => TableValue::False,
+			KTAB_TRUE => TableValue::True,
+			KTAB_INT => TableValue::Int(self.uleb128()? as i32),
+			KTAB_NUM => TableValue::Num(self.f64()?),
+			tag if tag >= KTAB_STR => TableValue::String(self.bytes((tag - KTAB_STR) as usize)?.to_vec()),
+			tag => return Err(ParseError::InvalidConstant { offset, tag }),
+		})
+	}
+
+	fn read_debug_info(
+		&mut self,
+		first_line: u32,
+		num_lines: u32,
+		num_instructions: usize,
+		num_upvalues: usize,
+		end: usize,
+	) -> Result<DebugInfo, ParseError> {
+		let mut lines = Vec::with_capacity(self.capacity(num_instructions));
+		for _ in 0..num_instructions {
+			let line = if num_lines < 256 {
+				self.byte()? as u32
+			} else if num_lines < 65536 {
+				self.u16()? as u32
+			} else {
+				self.u32()?
+			};
+			lines.push(first_line.wrapping_add(line));
+		}
+
+		let mut upvalue_names = Vec::with_capacity(self.capacity(num_upvalues));
+		for _ in 0..num_upvalues {
+			upvalue_names.push(self.c_string(end)?);
+		}
+
+		let mut variables = Vec::new();
+		let mut last_pc = 0u32;
+		loop {
+			let name = match self.data.get(self.pos).copied() {
+				Some(VARNAME_END) => {
+					self.pos += 1;
+					break;
+				}
+				Some(VARNAME_FOR_IDX) => VarName::ForIndex,
+				Some(VARNAME_FOR_STOP) => VarName::ForStop,
+				Some(VARNAME_FOR_STEP) => VarName::ForStep,
+				Some(VARNAME_FOR_GEN) => VarName::ForGenerator,
+				Some(VARNAME_FOR_STATE) => VarName::ForState,
+				Some(VARNAME_FOR_CTL) => VarName::ForControl,
+				Some(_) => VarName::Named(self.c_string(end)?),
+				None => return Err(ParseError::UnexpectedEof),
+			};
+			if !matches!(name, VarName::Named(_)) {
+				self.pos += 1;
+			}
+
+			let start_pc = last_pc.wrapping_add(self.uleb128()?);
+			let end_pc = start_pc.wrapping_add(self.uleb128()?);
+			last_pc = start_pc;
+
+			variables.push(VarInfo { name, start_pc, end_pc });
+		}
+
+		Ok(DebugInfo {
+			first_line,
+			num_lines,
+			lines,
+			upvalue_names,
+			variables,
+		})
+	}
+
+	/// Reads a NUL terminated string that must end before `end`.
+	fn c_string(&mut self, end: usize) -> Result<Vec<u8>, ParseError> {
+		let data = self.data.get(self.pos..end.min(self.data.len())).ok_or(ParseError::UnexpectedEof)?;
+		let len = data.iter().position(|byte| *byte == 0).ok_or(ParseError::UnexpectedEof)?;
+		let string = data[..len].to_vec();
+		self.pos += len + 1;
+		Ok(string)
+	}
+}
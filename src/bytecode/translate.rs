@@ -0,0 +1,354 @@
+//! Translation of bytecode between the formats of the 32-bit and 64-bit branches of Garry's Mod.
+//!
+//! Besides renumbering opcodes, LuaJIT 2.1 with two-slot frames (`LJ_FR2`) reserves an extra slot between a called function and its arguments,
+//! so every slot used while the arguments of a call are being evaluated is shifted up by one for each call that is being set up.
+//! Translating finds the instructions that set up each call and moves their slots accordingly.
+
+use super::*;
+
+/// The most slots a function can use (`LJ_MAX_SLOTS`).
+const MAX_SLOTS: u32 = 250;
+
+/// An error encountered while translating bytecode to the format of another branch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranslateError {
+	/// The bytecode couldn't be parsed.
+	Parse(ParseError),
+
+	/// The translated bytecode couldn't be written.
+	Write(WriteError),
+
+	/// An instruction has an opcode number that doesn't exist in its dump version.
+	UnknownOpcode { op: u8 },
+
+	/// An instruction's opcode doesn't exist in the target branch's version of LuaJIT.
+	///
+	/// These are only emitted by LuaJIT internally, never when compiling Lua source code.
+	UnsupportedOpcode(Opcode),
+
+	/// An instruction uses the slot two-slot frames reserve for a call's frame information, so its slots can't be translated.
+	InvalidFrameSlot { pc: usize },
+
+	/// The translated function would need more stack slots than LuaJIT allows.
+	TooManySlots,
+}
+impl std::fmt::Display for TranslateError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			TranslateError::Parse(error) => write!(f, "{}", error),
+			TranslateError::Write(error) => write!(f, "{}", error),
+			TranslateError::UnknownOpcode { op } => write!(f, "unknown opcode {}", op),
+			TranslateError::UnsupportedOpcode(opcode) => write!(f, "{} isn't supported by the target branch", opcode.name()),
+			TranslateError::InvalidFrameSlot { pc } => write!(f, "instruction {} uses the frame slot of a call", pc),
+			TranslateError::TooManySlots => write!(f, "translated function needs more than {} stack slots", MAX_SLOTS),
+		}
+	}
+}
+impl std::error::Error for TranslateError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			TranslateError::Parse(error) => Some(error),
+			TranslateError::Write(error) => Some(error),
+			_ => None,
+		}
+	}
+}
+impl From<ParseError> for TranslateError {
+	fn from(error: ParseError) -> Self {
+		TranslateError::Parse(error)
+	}
+}
+impl From<WriteError> for TranslateError {
+	fn from(error: WriteError) -> Self {
+		TranslateError::Write(error)
+	}
+}
+
+/// The format instructions are encoded in.
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+	version: u8,
+	fr2: bool,
+}
+
+/// Translates bytecode to the format loaded by the given branch. See [`BytecodeDump::translate`].
+pub fn translate(bytecode: &[u8], branch: Branch) -> Result<crate::Bytecode, TranslateError> {
+	Ok(BytecodeDump::parse(bytecode)?.translate(branch)?.write()?)
+}
+
+impl BytecodeDump {
+	/// Translates this dump to the format loaded by the given branch of Garry's Mod, so that bytecode compiled by one branch can be loaded by the other.
+	///
+	/// Opcodes are renumbered and slots are moved to add or remove the extra slot two-slot frames reserve in calls. Debug information is kept as-is.
+	/// Translating bytecode to the branch it was compiled by only converts it to little-endian.
+	///
+	/// The result is equivalent to, but not necessarily identical to, compiling the same source with the other branch.
+	///
+	/// This is experimental: the slots of calls and iterators are moved by following the instructions in order rather than the control flow,
+	/// which hasn't yet been checked against bytecode compiled by the 32-bit branch beyond simple cases.
+	pub fn translate(&self, branch: Branch) -> Result<BytecodeDump, TranslateError> {
+		let from = Layout {
+			version: self.header.version,
+			fr2: self.header.is_fr2(),
+		};
+		let to = Layout {
+			version: branch.version(),
+			fr2: branch.fr2(),
+		};
+
+		let mut header = self.header.clone();
+		header.version = to.version;
+		header.flags.set(HeaderFlags::FR2, to.fr2);
+		header.flags.set(HeaderFlags::BIG_ENDIAN, false);
+
+		Ok(BytecodeDump {
+			header,
+			main: translate_proto(&self.main, from, to)?,
+		})
+	}
+}
+
+fn translate_proto(proto: &Prototype, from: Layout, to: Layout) -> Result<Prototype, TranslateError> {
+	let decoded = proto
+		.instructions
+		.iter()
+		.map(|instruction| {
+			let opcode = instruction
+				.opcode(from.version)
+				.ok_or(TranslateError::UnknownOpcode { op: instruction.op() })?;
+			opcode.to_u8(to.version).ok_or(TranslateError::UnsupportedOpcode(opcode))?;
+			Ok((opcode, *instruction))
+		})
+		.collect::<Result<Vec<_>, TranslateError>>()?;
+
+	let shift = match (from.fr2, to.fr2) {
+		(false, true) => Some(true),
+		(true, false) => Some(false),
+		_ => None,
+	};
+
+	let mut instructions = Vec::with_capacity(decoded.len());
+	match shift {
+		Some(up) => {
+			let calls = open_calls(&decoded, from.fr2);
+			let mut starts = vec![Vec::new(); decoded.len()];
+			for call in &calls {
+				starts[call.start].push(call);
+			}
+
+			let mut open: Vec<&OpenCall> = Vec::new();
+			for (pc, (opcode, instruction)) in decoded.iter().enumerate() {
+				open.extend(starts[pc].iter().copied());
+				open.retain(|call| call.pc != pc);
+
+				let bases = open.iter().map(|call| call.base).collect::<Vec<_>>();
+				let slot = |slot: u32| shift_slot(slot, &bases, up).ok_or(TranslateError::InvalidFrameSlot { pc });
+				instructions.push(map_slots(*opcode, *instruction, to.version, slot)?);
+			}
+		}
+		None => {
+			for (opcode, instruction) in &decoded {
+				instructions.push(map_slots(*opcode, *instruction, to.version, Ok)?);
+			}
+		}
+	}
+
+	// Keep any slots the function reserved beyond the ones its instructions use
+	let used_before = decoded
+		.iter()
+		.map(|(opcode, instruction)| slots_used(*opcode, *instruction, from.fr2))
+		.max()
+		.unwrap_or(0);
+	let used_after = instructions
+		.iter()
+		.zip(&decoded)
+		.map(|(instruction, (opcode, _))| slots_used(*opcode, *instruction, to.fr2))
+		.max()
+		.unwrap_or(0);
+	let frame_size = (proto.frame_size as u32 + used_after).saturating_sub(used_before).max(used_after);
+	if frame_size > MAX_SLOTS {
+		return Err(TranslateError::TooManySlots);
+	}
+
+	let gc_constants = proto
+		.gc_constants
+		.iter()
+		.map(|constant| {
+			Ok(match constant {
+				GcConstant::Child(child) => GcConstant::Child(translate_proto(child, from, to)?),
+				constant => constant.clone(),
+			})
+		})
+		.collect::<Result<Vec<_>, TranslateError>>()?;
+
+	Ok(Prototype {
+		flags: proto.flags,
+		num_params: proto.num_params,
+		frame_size: frame_size as u8,
+		instructions,
+		upvalues: proto.upvalues.clone(),
+		gc_constants,
+		num_constants: proto.num_constants.clone(),
+		debug: proto.debug.clone(),
+	})
+}
+
+/// A call whose function and arguments are being set up.
+#[derive(Debug)]
+struct OpenCall {
+	/// The slot the function is called from.
+	base: u32,
+
+	/// The first instruction that sets up the call's arguments.
+	start: usize,
+
+	/// The call instruction.
+	pc: usize,
+}
+
+/// Finds the instructions that set up the arguments of each call.
+///
+/// The function is always stored to the call's base slot right before its arguments are evaluated into the slots above it, and nothing else is stored to the base slot until the call.
+fn open_calls(decoded: &[(Opcode, Instruction)], fr2: bool) -> Vec<OpenCall> {
+	let fr2 = fr2 as u32;
+	let mut calls = Vec::new();
+	for (pc, (opcode, instruction)) in decoded.iter().enumerate() {
+		if !matches!(opcode, Opcode::Call | Opcode::CallM | Opcode::CallT | Opcode::CallMT) {
+			continue;
+		}
+
+		let base = instruction.a() as u32;
+		let function = decoded[..pc]
+			.iter()
+			.rposition(|(opcode, instruction)| writes_slot(*opcode, *instruction, base));
+
+		let start = match function {
+			Some(function) => {
+				// Method calls copy the object into the first argument before looking up the method
+				let (lookup, lookup_instruction) = decoded[function];
+				let is_self = |index: Option<usize>, obj: u32| match index.map(|index| decoded[index]) {
+					Some((Opcode::Mov, mov)) => mov.a() as u32 == base + 1 + fr2 && mov.d() as u32 == obj,
+					_ => false,
+				};
+				let obj = lookup_instruction.b() as u32;
+
+				if lookup == Opcode::TGetS && is_self(function.checked_sub(1), obj) {
+					function - 1
+				} else if lookup == Opcode::TGetV
+					&& lookup_instruction.c() as u32 == base + 2 + fr2
+					&& matches!(function.checked_sub(1).map(|index| decoded[index]), Some((Opcode::KStr, kstr)) if kstr.a() as u32 == base + 2 + fr2)
+					&& is_self(function.checked_sub(2), obj)
+				{
+					function - 2
+				} else {
+					function + 1
+				}
+			}
+			None => 0,
+		};
+
+		calls.push(OpenCall { base, start, pc });
+	}
+	calls
+}
+
+/// Whether an instruction stores a value to a slot.
+fn writes_slot(opcode: Opcode, instruction: Instruction, slot: u32) -> bool {
+	let a = instruction.a() as u32;
+	let b = instruction.b() as u32;
+	match opcode {
+		Opcode::KNil => a <= slot && slot <= instruction.d() as u32,
+		Opcode::Call | Opcode::CallM | Opcode::VArg => slot >= a && (b == 0 || slot < a + b - 1),
+		Opcode::IterC | Opcode::IterN => slot >= a && (b == 0 || slot < (a + b - 1).max(a + 3)),
+		Opcode::ForI | Opcode::JForI | Opcode::ForL | Opcode::IForL | Opcode::JForL => slot == a || slot == a + 3,
+		Opcode::IterL | Opcode::IIterL | Opcode::JIterL => slot + 1 == a,
+		_ => opcode.modes().0 == OperandMode::Dst && a == slot,
+	}
+}
+
+/// Moves a slot up (adding two-slot frames) or down (removing them) past the frame slot of each call being set up below it.
+///
+/// Returns `None` if the slot is the frame slot of one of the calls.
+fn shift_slot(slot: u32, bases: &[u32], up: bool) -> Option<u32> {
+	if up {
+		Some(slot + bases.iter().filter(|base| **base < slot).count() as u32)
+	} else {
+		if bases.iter().any(|base| *base + 1 == slot) {
+			return None;
+		}
+		Some(slot - bases.iter().filter(|base| *base + 1 < slot).count() as u32)
+	}
+}
+
+fn is_slot(mode: OperandMode) -> bool {
+	matches!(mode, OperandMode::Dst | OperandMode::Base | OperandMode::Var | OperandMode::RBase)
+}
+
+/// Re-encodes an instruction for another dump version, mapping its slot operands.
+fn map_slots<F>(opcode: Opcode, instruction: Instruction, version: u8, mut slot: F) -> Result<Instruction, TranslateError>
+where
+	F: FnMut(u32) -> Result<u32, TranslateError>,
+{
+	let (mode_a, mode_b, mode_cd) = opcode.modes();
+	let op = opcode.to_u8(version).ok_or(TranslateError::UnsupportedOpcode(opcode))?;
+
+	let mut operand = |mode: OperandMode, value: u32, max: u32| -> Result<u32, TranslateError> {
+		if !is_slot(mode) {
+			return Ok(value);
+		}
+		let value = slot(value)?;
+		if value > max || value >= MAX_SLOTS {
+			return Err(TranslateError::TooManySlots);
+		}
+		Ok(value)
+	};
+
+	let a = operand(mode_a, instruction.a() as u32, u8::MAX as u32)? as u8;
+	Ok(if opcode.has_b() {
+		let b = operand(mode_b, instruction.b() as u32, u8::MAX as u32)? as u8;
+		let c = operand(mode_cd, instruction.c() as u32, u8::MAX as u32)? as u8;
+		Instruction::new_abc(op, a, b, c)
+	} else {
+		let d = operand(mode_cd, instruction.d() as u32, u16::MAX as u32)? as u16;
+		Instruction::new_ad(op, a, d)
+	})
+}
+
+/// The number of stack slots an instruction needs.
+fn slots_used(opcode: Opcode, instruction: Instruction, fr2: bool) -> u32 {
+	let fr2 = fr2 as u32;
+	let a = instruction.a() as u32;
+	let b = instruction.b() as u32;
+	let c = instruction.c() as u32;
+	let d = instruction.d() as u32;
+	match opcode {
+		Opcode::Call | Opcode::CallM => (a + b).saturating_sub(1).max(a + c + fr2).max(a + 1 + fr2),
+		Opcode::CallT | Opcode::CallMT => (a + d + fr2).max(a + 1 + fr2),
+		Opcode::IterC | Opcode::IterN => (a + b).saturating_sub(1).max(a + 3 + fr2),
+		Opcode::VArg => (a + b).saturating_sub(1).max(a),
+		Opcode::KNil => d + 1,
+		Opcode::ForI | Opcode::JForI | Opcode::ForL | Opcode::IForL | Opcode::JForL => a + 4,
+		Opcode::Ret => (a + d).saturating_sub(1),
+		Opcode::RetM => a + d,
+		// The A operand of these is only a hint of which slots are in use
+		Opcode::Ret0 | Opcode::UClo | Opcode::Jmp | Opcode::Loop | Opcode::ILoop | Opcode::JLoop => 0,
+		_ => {
+			let (mode_a, mode_b, mode_cd) = opcode.modes();
+			let mut used = 0;
+			if is_slot(mode_a) {
+				used = used.max(a + 1);
+			}
+			if opcode.has_b() {
+				if is_slot(mode_b) {
+					used = used.max(b + 1);
+				}
+				if is_slot(mode_cd) {
+					used = used.max(c + 1);
+				}
+			} else if is_slot(mode_cd) {
+				used = used.max(d + 1);
+			}
+			used
+		}
+	}
+}
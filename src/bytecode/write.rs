@@ -0,0 +1,301 @@
+use std::convert::TryFrom;
+
+use super::{read::*, *};
+
+pub(super) struct Writer {
+	buf: Vec<u8>,
+	big_endian: bool,
+	stripped: bool,
+}
+impl Writer {
+	pub(super) fn new(header: &Header) -> Self {
+		Self {
+			buf: Vec::new(),
+			big_endian: header.flags.contains(HeaderFlags::BIG_ENDIAN),
+			stripped: header.flags.contains(HeaderFlags::STRIP),
+		}
+	}
+
+	/// Creates an empty writer with the same settings, for sections that need to be prefixed with their length.
+	fn nested(&self) -> Writer {
+		Writer {
+			buf: Vec::new(),
+			big_endian: self.big_endian,
+			stripped: self.stripped,
+		}
+	}
+
+	fn byte(&mut self, byte: u8) {
+		self.buf.push(byte);
+	}
+
+	fn bytes(&mut self, bytes: &[u8]) {
+		self.buf.extend_from_slice(bytes);
+	}
+
+	fn u16(&mut self, value: u16) {
+		if self.big_endian {
+			self.bytes(&value.to_be_bytes());
+		} else {
+			self.bytes(&value.to_le_bytes());
+		}
+	}
+
+	fn u32(&mut self, value: u32) {
+		if self.big_endian {
+			self.bytes(&value.to_be_bytes());
+		} else {
+			self.bytes(&value.to_le_bytes());
+		}
+	}
+
+	fn uleb128_wide(&mut self, mut value: u64) {
+		loop {
+			let byte = (value & 0x7F) as u8;
+			value >>= 7;
+			if value == 0 {
+				self.byte(byte);
+				return;
+			}
+			self.byte(byte | 0x80);
+		}
+	}
+
+	fn uleb128(&mut self, value: u32) {
+		self.uleb128_wide(value as u64);
+	}
+
+	fn len(&mut self, len: usize) -> Result<(), WriteError> {
+		self.uleb128(u32::try_from(len).map_err(|_| WriteError::TooLarge)?);
+		Ok(())
+	}
+
+	fn f64(&mut self, value: f64) {
+		self.u64(value.to_bits());
+	}
+
+	fn u64(&mut self, value: u64) {
+		self.uleb128(value as u32);
+		self.uleb128((value >> 32) as u32);
+	}
+
+	pub(super) fn write_dump(mut self, dump: &BytecodeDump) -> Result<Vec<u8>, WriteError> {
+		self.write_header(&dump.header)?;
+		self.write_proto(&dump.main)?;
+		self.byte(0);
+		Ok(self.buf)
+	}
+
+	fn write_header(&mut self, header: &Header) -> Result<(), WriteError> {
+		self.bytes(SIGNATURE);
+		self.byte(header.version);
+		self.uleb128(header.flags.0);
+
+		if !self.stripped {
+			let chunk_name = header.chunk_name.as_deref().unwrap_or_default();
+			self.len(chunk_name.len())?;
+			self.bytes(chunk_name);
+		}
+
+		Ok(())
+	}
+
+	fn write_proto(&mut self, proto: &Prototype) -> Result<(), WriteError> {
+		// Children must be written before their parent, in the order they are popped off the stack again when read
+		for child in proto.children() {
+			self.write_proto(child)?;
+		}
+
+		let mut body = self.nested();
+		body.write_proto_body(proto)?;
+
+		self.len(body.buf.len())?;
+		self.bytes(&body.buf);
+		Ok(())
+	}
+
+	fn write_proto_body(&mut self, proto: &Prototype) -> Result<(), WriteError> {
+		let num_upvalues = u8::try_from(proto.upvalues.len()).map_err(|_| WriteError::TooManyUpvalues(proto.upvalues.len()))?;
+
+		let debug = match &proto.debug {
+			Some(debug) if !self.stripped => {
+				if debug.lines.len() != proto.instructions.len() || debug.upvalue_names.len() != proto.upvalues.len() {
+					return Err(WriteError::InvalidDebugInfo);
+				}
+
+				let mut writer = self.nested();
+				writer.write_debug_info(debug)?;
+				Some((debug, writer.buf))
+			}
+			_ => None,
+		};
+
+		self.byte(proto.flags.0);
+		self.byte(proto.num_params);
+		self.byte(proto.frame_size);
+		self.byte(num_upvalues);
+		self.len(proto.gc_constants.len())?;
+		self.len(proto.num_constants.len())?;
+		self.len(proto.instructions.len())?;
+
+		if !self.stripped {
+			match &debug {
+				Some((debug, buf)) => {
+					self.len(buf.len())?;
+					self.uleb128(debug.first_line);
+					self.uleb128(debug.num_lines);
+				}
+				None => self.byte(0),
+			}
+		}
+
+		for instruction in &proto.instructions {
+			self.u32(instruction.0);
+		}
+
+		for upvalue in &proto.upvalues {
+			self.u16(upvalue.0);
+		}
+
+		for constant in proto.gc_constants.iter().rev() {
+			self.write_gc_constant(constant)?;
+		}
+
+		for constant in &proto.num_constants {
+			match *constant {
+				NumConstant::Int(int) => self.uleb128_wide((int as u32 as u64) << 1),
+				NumConstant::Num(num) => {
+					let bits = num.to_bits();
+					self.uleb128_wide((bits as u32 as u64) << 1 | 1);
+					self.uleb128((bits >> 32) as u32);
+				}
+			}
+		}
+
+		if let Some((_, buf)) = debug {
+			self.bytes(&buf);
+		}
+
+		Ok(())
+	}
+
+	fn write_gc_constant(&mut self, constant: &GcConstant) -> Result<(), WriteError> {
+		match constant {
+			GcConstant::Child(_) => self.uleb128(KGC_CHILD),
+			GcConstant::Table(table) => {
+				self.uleb128(KGC_TAB);
+				self.write_table(table)?;
+			}
+			GcConstant::I64(int) => {
+				self.uleb128(KGC_I64);
+				self.u64(*int as u64);
+			}
+			GcConstant::U64(int) => {
+				self.uleb128(KGC_U64);
+				self.u64(*int);
+			}
+			GcConstant::Complex(re, im) => {
+				self.uleb128(KGC_COMPLEX);
+				self.f64(*re);
+				self.f64(*im);
+			}
+			GcConstant::String(string) => self.string(KGC_STR, string)?,
+		}
+		Ok(())
+	}
+
+	fn write_table(&mut self, table: &TableConstant) -> Result<(), WriteError> {
+		self.len(table.array.len())?;
+		self.len(table.hash.len())?;
+		for value in &table.array {
+			self.write_table_value(value)?;
+		}
+		for (key, value) in &table.hash {
+			self.write_table_value(key)?;
+			self.write_table_value(value)?;
+		}
+		Ok(())
+	}
+
+	fn write_table_value(&mut self, value: &TableValue) -> Result<(), WriteError> {
+		match value {
+			TableValue::Nil => self.uleb128(KTAB_NIL),
+			TableValue::False => self.uleb128(KTAB_FALSE),
+			TableValue::True => self.uleb128(KTAB_TRUE),
+			TableValue::Int(int) => {
+				self.uleb128(KTAB_INT);
+				self.uleb128(*int as u32);
+			}
+			TableValue::Num(num) => {
+				self.uleb128(KTAB_NUM);
+				self.f64(*num);
+			}
+			TableValue::String(string) => self.string(KTAB_STR, string)?,
+		}
+		Ok(())
+	}
+
+	/// Writes a string tagged with `tag + len`.
+	fn string(&mut self, tag: u32, string: &[u8]) -> Result<(), WriteError> {
+		let tag = u32::try_from(string.len())
+			.ok()
+			.and_then(|len| len.checked_add(tag))
+			.ok_or(WriteError::TooLarge)?;
+		self.uleb128(tag);
+		self.bytes(string);
+		Ok(())
+	}
+
+	fn write_debug_info(&mut self, debug: &DebugInfo) -> Result<(), WriteError> {
+		for line in &debug.lines {
+			let line = line.wrapping_sub(debug.first_line);
+			if debug.num_lines < 256 {
+				self.byte(line as u8);
+			} else if debug.num_lines < 65536 {
+				self.u16(line as u16);
+			} else {
+				self.u32(line);
+			}
+		}
+
+		for name in &debug.upvalue_names {
+			self.c_string(name)?;
+		}
+
+		let mut last_pc = 0u32;
+		for variable in &debug.variables {
+			match &variable.name {
+				VarName::Named(name) => {
+					// Empty names and names starting with a VARNAME_* byte would be read back as something else
+					if name.first().map(|byte| *byte <= VARNAME_FOR_CTL).unwrap_or(true) {
+						return Err(WriteError::InvalidDebugInfo);
+					}
+					self.c_string(name)?;
+				}
+				VarName::ForIndex => self.byte(VARNAME_FOR_IDX),
+				VarName::ForStop => self.byte(VARNAME_FOR_STOP),
+				VarName::ForStep => self.byte(VARNAME_FOR_STEP),
+				VarName::ForGenerator => self.byte(VARNAME_FOR_GEN),
+				VarName::ForState => self.byte(VARNAME_FOR_STATE),
+				VarName::ForControl => self.byte(VARNAME_FOR_CTL),
+			}
+
+			self.uleb128(variable.start_pc.wrapping_sub(last_pc));
+			self.uleb128(variable.end_pc.wrapping_sub(variable.start_pc));
+			last_pc = variable.start_pc;
+		}
+		self.byte(VARNAME_END);
+
+		Ok(())
+	}
+
+	/// Writes a NUL terminated string.
+	fn c_string(&mut self, string: &[u8]) -> Result<(), WriteError> {
+		if string.contains(&0) {
+			return Err(WriteError::InvalidDebugInfo);
+		}
+		self.bytes(string);
+		self.byte(0);
+		Ok(())
+	}
+}
@@ -0,0 +1,164 @@
+use std::{
+	io::ErrorKind,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		PoisonError,
+	},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{bytecode::SIGNATURE, Bytecode, BytecodeCompiler, LuaError};
+
+/// Bumped whenever the way cache keys are computed or entries are stored changes.
+const CACHE_VERSION: &[u8] = b"gluac-rs cache v2\0";
+
+/// An on-disk cache of compiled bytecode, so that sources that haven't changed since they were last compiled don't need to be compiled again.
+///
+/// Entries are keyed on the source code, the chunk name, whether debug information was stripped, whether the compiler preprocesses GLua and
+/// the `lua_shared` library that compiled them, so changing any of them (e.g. after a Garry's Mod update) compiles the source again.
+///
+/// Failed compilations aren't cached, and neither is anything compiled by a `lua_shared` whose file can't be read, as then there's no telling
+/// which version compiled an entry.
+///
+/// ```no_run
+/// let compiler = gluac_rs::compiler().unwrap();
+/// let cache = gluac_rs::CompileCache::new(".gluac-cache");
+///
+/// let bytecode = cache.compile_named(&compiler, "print('Hello, world!')", "@lua/autorun/hello.lua", false).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct CompileCache {
+	dir: PathBuf,
+	hits: AtomicUsize,
+	misses: AtomicUsize,
+	library_hashes: std::sync::Mutex<Vec<(PathBuf, Option<[u8; 32]>)>>,
+}
+impl CompileCache {
+	/// Creates a cache that stores its entries in the given directory. The directory is created when the first entry is stored.
+	pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+		Self {
+			dir: dir.into(),
+			hits: AtomicUsize::new(0),
+			misses: AtomicUsize::new(0),
+			library_hashes: std::sync::Mutex::new(Vec::new()),
+		}
+	}
+
+	/// Returns the directory this cache stores its entries in.
+	pub fn dir(&self) -> &Path {
+		&self.dir
+	}
+
+	/// Returns how many compilations were skipped because their bytecode was cached.
+	pub fn hits(&self) -> usize {
+		self.hits.load(Ordering::Relaxed)
+	}
+
+	/// Returns how many compilations weren't cached.
+	pub fn misses(&self) -> usize {
+		self.misses.load(Ordering::Relaxed)
+	}
+
+	/// Returns the cached bytecode for this source, or compiles it with [`BytecodeCompiler::compile_named`] and caches it.
+	///
+	/// ## Errors
+	/// Returns `LuaError::IoError` if the bytecode couldn't be written to the cache.
+	pub fn compile_named<S: AsRef<[u8]>>(
+		&self,
+		compiler: &BytecodeCompiler,
+		src: S,
+		chunk_name: &str,
+		strip_debug: bool,
+	) -> Result<Bytecode, LuaError> {
+		let src = src.as_ref();
+		let path = match self.entry_path(compiler, src, chunk_name, strip_debug) {
+			Some(path) => path,
+			None => {
+				self.misses.fetch_add(1, Ordering::Relaxed);
+				return compiler.compile_named(src, chunk_name, strip_debug);
+			}
+		};
+
+		match std::fs::read(&path) {
+			Ok(bytecode) if bytecode.starts_with(SIGNATURE) => {
+				self.hits.fetch_add(1, Ordering::Relaxed);
+				return Ok(bytecode);
+			}
+			_ => {
+				self.misses.fetch_add(1, Ordering::Relaxed);
+			}
+		}
+
+		let bytecode = compiler.compile_named(src, chunk_name, strip_debug)?;
+		self.store(&path, &bytecode)?;
+		Ok(bytecode)
+	}
+
+	/// Deletes every entry in the cache.
+	pub fn clear(&self) -> Result<(), LuaError> {
+		match std::fs::remove_dir_all(&self.dir) {
+			Err(error) if error.kind() != ErrorKind::NotFound => Err(error.into()),
+			_ => Ok(()),
+		}
+	}
+
+	fn entry_path(&self, compiler: &BytecodeCompiler, src: &[u8], chunk_name: &str, strip_debug: bool) -> Option<PathBuf> {
+		let mut key = Sha256::new();
+		key.update(CACHE_VERSION);
+		key.update(self.library_hash(compiler.library_path())?);
+		key.update([strip_debug as u8]);
+		key.update([compiler.is_glua_preprocessed() as u8]);
+		key.update((chunk_name.len() as u64).to_le_bytes());
+		key.update(chunk_name);
+		key.update(Sha256::digest(src));
+
+		let key = hex(&key.finalize());
+		Some(self.dir.join(&key[..2]).join(key))
+	}
+
+	/// Hashes the contents of a `lua_shared`, so that entries compiled by a different version aren't used.
+	///
+	/// Returns `None` if the library's file can't be read. Its path is usually where the dynamic linker found it, but if that couldn't be
+	/// found out, it may just be a name that the file can't be read by.
+	pub(crate) fn library_hash(&self, library: &Path) -> Option<[u8; 32]> {
+		let mut library_hashes = self.library_hashes.lock().unwrap_or_else(PoisonError::into_inner);
+		if let Some((_, hash)) = library_hashes.iter().find(|(path, _)| path == library) {
+			return *hash;
+		}
+
+		let hash = match library.parent() {
+			Some(dir) if !dir.as_os_str().is_empty() => std::fs::read(library).ok().map(|contents| Sha256::digest(contents).into()),
+			_ => None,
+		};
+
+		library_hashes.push((library.to_path_buf(), hash));
+		hash
+	}
+
+	fn store(&self, path: &Path, bytecode: &[u8]) -> Result<(), LuaError> {
+		let dir = path.parent().unwrap();
+		std::fs::create_dir_all(dir)?;
+
+		// Write to a temporary file first so that other processes never see a partially written entry
+		static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+		let tmp_path = dir.join(format!(
+			".{}.{}.{}.tmp",
+			path.file_name().unwrap().to_string_lossy(),
+			std::process::id(),
+			TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+		));
+		std::fs::write(&tmp_path, bytecode)?;
+		if let Err(error) = std::fs::rename(&tmp_path, path) {
+			std::fs::remove_file(&tmp_path).ok();
+			return Err(error.into());
+		}
+
+		Ok(())
+	}
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
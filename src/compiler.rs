@@ -1,13 +1,137 @@
+use std::{
+	ffi::{CStr, CString},
+	num::NonZeroUsize,
+	path::{Path, PathBuf},
+};
+
 use crate::{
+	backend::{self, Backend},
+	glua,
+	library::LibrarySearch,
 	lua::{self, LuaString, LUA_GLOBALSINDEX},
-	lua_string, Bytecode, LuaError, Mutex, MutexGuard,
+	Bytecode, CompilerPool, LuaError, Mutex, MutexGuard,
 };
 
+/// Configures where `lua_shared` is loaded from and creates a `BytecodeCompiler`.
+///
+/// Unless another [`backend`](CompilerBuilder::backend) is chosen, `lua_shared` is searched for in this order:
+///
+/// 1. Paths given to [`library_path`](CompilerBuilder::library_path)
+/// 2. Paths in the `GLUAC_LUA_SHARED` environment variable (separated like `PATH`)
+/// 3. The executable's directory, then the system's library search path
+/// 4. Garry's Mod installations given to [`gmod_path`](CompilerBuilder::gmod_path), then any found in Steam library folders
+///
+/// Each `lua_shared` is only loaded once per process; building another compiler that finds the same library reuses it.
+#[derive(Debug, Clone, Default)]
+pub struct CompilerBuilder {
+	search: LibrarySearch,
+	backend: Option<std::sync::Arc<dyn Backend>>,
+	glua: Option<bool>,
+	pool_size: Option<usize>,
+}
+impl CompilerBuilder {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds a path to `lua_shared`, or to a directory containing it.
+	///
+	/// These are tried before anywhere else, in the order they were added.
+	pub fn library_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+		self.search.library_paths.push(path.into());
+		self
+	}
+
+	/// Adds the root directory of a Garry's Mod (or Garry's Mod dedicated server) installation to search for `lua_shared` in.
+	///
+	/// The correct `bin` directory for the target's pointer width (e.g. `bin/linux64`, `bin/win64`, `garrysmod/bin`) is searched.
+	pub fn gmod_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+		self.search.gmod_paths.push(path.into());
+		self
+	}
+
+	/// Whether to read paths from the `GLUAC_LUA_SHARED` environment variable. Enabled by default.
+	pub fn env(mut self, env: bool) -> Self {
+		self.search.env = env;
+		self
+	}
+
+	/// Whether to search for Garry's Mod installations in Steam library folders. Enabled by default.
+	pub fn auto_probe(mut self, auto_probe: bool) -> Self {
+		self.search.auto_probe = auto_probe;
+		self
+	}
+
+	/// Compiles with another backend instead of Garry's Mod's `lua_shared`, such as a stock LuaJIT. See [`backend`](crate::backend).
+	///
+	/// The other options of this builder that choose where `lua_shared` is loaded from are ignored.
+	pub fn backend<B: Backend + 'static>(mut self, backend: B) -> Self {
+		self.backend = Some(std::sync::Arc::new(backend));
+		self
+	}
+
+	/// Whether to rewrite Garry's Mod's syntax extensions to Lua into standard Lua before compiling, see [`glua`](crate::glua).
+	///
+	/// By default this is only done if the backend doesn't support them itself, i.e. when not compiling with `lua_shared`.
+	pub fn glua(mut self, preprocess: bool) -> Self {
+		self.glua = Some(preprocess);
+		self
+	}
+
+	/// Loads the backend, returning its Lua C API and whether GLua needs to be preprocessed.
+	fn load(&self) -> Result<(&'static lua::LuaApi, bool), LuaError> {
+		let lua_shared;
+		let backend: &dyn Backend = match &self.backend {
+			Some(backend) => &**backend,
+			None => {
+				lua_shared = backend::LuaShared(self.search.clone());
+				&lua_shared
+			}
+		};
+		Ok((backend.load()?, self.glua.unwrap_or(!backend.supports_glua())))
+	}
+
+	/// Loads `lua_shared` and creates the bytecode compiler.
+	pub fn build(&self) -> Result<BytecodeCompiler, LuaError> {
+		let (api, glua) = self.load()?;
+		unsafe { BytecodeCompiler::new(api, glua) }
+	}
+
+	/// Sets the number of Lua states in a [`CompilerPool`] created by [`build_pool`](CompilerBuilder::build_pool).
+	///
+	/// Defaults to the number of CPUs available.
+	pub fn pool_size(mut self, size: usize) -> Self {
+		self.pool_size = Some(size);
+		self
+	}
+
+	/// Loads `lua_shared` and creates a pool of bytecode compilers, each with their own Lua state.
+	pub fn build_pool(&self) -> Result<CompilerPool, LuaError> {
+		let size = self
+			.pool_size
+			.unwrap_or_else(|| std::thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1))
+			.max(1);
+
+		let (api, glua) = self.load()?;
+		let compilers = (0..size)
+			.map(|_| unsafe { BytecodeCompiler::new(api, glua) })
+			.collect::<Result<Vec<_>, _>>()?;
+
+		Ok(CompilerPool::new(compilers))
+	}
+}
+
 #[derive(Debug)]
-pub struct BytecodeCompiler(Mutex<lua::LuaState>);
+pub struct BytecodeCompiler {
+	state: Mutex<lua::LuaState>,
+	api: &'static lua::LuaApi,
+
+	/// Whether GLua's syntax extensions are rewritten before compiling.
+	glua: bool,
+}
 impl BytecodeCompiler {
-	pub(crate) unsafe fn new() -> Result<Self, LuaError> {
-		let lua_state = lua::LuaState::new()?;
+	pub(crate) unsafe fn new(api: &'static lua::LuaApi, glua: bool) -> Result<Self, LuaError> {
+		let lua_state = lua::LuaState::new(api)?;
 
 		// Push string.dump onto the stack
 		lua_state.get_field(LUA_GLOBALSINDEX, lua_string!("string"));
@@ -15,26 +139,30 @@ impl BytecodeCompiler {
 
 		lua_state.push_value(-1); // Copy the string.dump reference onto the stack again (saves us getting it from _G every time)
 
-		Ok(Self(Mutex::new(lua_state)))
+		Ok(Self {
+			state: Mutex::new(lua_state),
+			api,
+			glua,
+		})
 	}
 
 	#[cfg(feature = "parking_lot")]
 	#[inline]
 	fn lock(&self) -> Result<MutexGuard<'_, lua::LuaState>, LuaError> {
-		Ok(self.0.lock())
+		Ok(self.state.lock())
 	}
 
 	#[cfg(not(feature = "parking_lot"))]
 	#[inline]
 	fn lock(&self) -> Result<MutexGuard<'_, lua::LuaState>, LuaError> {
-		self.0.lock().map_err(|_| LuaError::PoisonError)
+		self.state.lock().map_err(|_| LuaError::PoisonError)
 	}
 
 	#[cfg(feature = "parking_lot")]
 	#[inline]
 	/// Returns if the Mutex guarding the underlying Lua state is currently locked.
 	pub fn is_locked(&self) -> bool {
-		self.0.is_locked()
+		self.state.is_locked()
 	}
 
 	#[cfg(not(feature = "parking_lot"))]
@@ -42,12 +170,61 @@ impl BytecodeCompiler {
 	///
 	/// This is currently implemented using `std::sync::Mutex::try_lock()` and matching against `TryLockError::WouldBlock`
 	pub fn is_locked(&self) -> bool {
-		match self.0.try_lock() {
+		match self.state.try_lock() {
 			Ok(_) => false,
 			Err(err) => matches!(err, std::sync::TryLockError::WouldBlock),
 		}
 	}
 
+	/// Returns the path of the `lua_shared` library this compiler is using.
+	///
+	/// For the LuaJIT linked in by the `luajit` feature, this is the path of the executable.
+	pub fn library_path(&self) -> &Path {
+		self.api.path()
+	}
+
+	/// Whether GLua's syntax extensions are rewritten into standard Lua before compiling, see [`CompilerBuilder::glua`].
+	pub fn is_glua_preprocessed(&self) -> bool {
+		self.glua
+	}
+
+	/// Loads source code with `luaL_loadbuffer`, rewriting GLua's syntax extensions first if needed.
+	unsafe fn load_buffer(&self, lua_state: lua::LuaState, src: &[u8], chunk_name: LuaString) -> Result<(), LuaError> {
+		if self.glua {
+			lua_state.load_buffer(&glua::to_lua(src), chunk_name)
+		} else {
+			lua_state.load_buffer(src, chunk_name)
+		}
+	}
+
+	/// Loads source code with `luaL_loadstring`, or like it if GLua's syntax extensions need to be rewritten first.
+	unsafe fn load_string(&self, lua_state: lua::LuaState, src: LuaString) -> Result<(), LuaError> {
+		if self.glua {
+			// luaL_loadstring uses the source code as the chunk name
+			lua_state.load_buffer(&glua::to_lua(CStr::from_ptr(src).to_bytes()), src)
+		} else {
+			lua_state.load_string(src)
+		}
+	}
+
+	/// Loads a file with `luaL_loadfile`, or like it if GLua's syntax extensions need to be rewritten first.
+	unsafe fn load_file(&self, lua_state: lua::LuaState, path: LuaString) -> Result<(), LuaError> {
+		if !self.glua {
+			return lua_state.load_file(path);
+		}
+
+		let path = CStr::from_ptr(path);
+		let src = std::fs::read(cstr_to_path(path))
+			.map_err(|error| LuaError::FileError(Some(format!("cannot open {}: {}", path.to_string_lossy(), os_error_message(&error)))))?;
+
+		let mut chunk_name = Vec::with_capacity(path.to_bytes().len() + 2);
+		chunk_name.push(b'@');
+		chunk_name.extend_from_slice(path.to_bytes());
+		let chunk_name = CString::new(chunk_name)?;
+
+		lua_state.load_buffer(&glua::to_lua(&src), chunk_name.as_ptr())
+	}
+
 	unsafe fn compile(&self, lua_state: lua::LuaState, strip_debug: bool) -> Result<Bytecode, LuaError> {
 		lua_state.push_boolean(strip_debug); // Push strip_debug argument onto the stack
 
@@ -63,13 +240,70 @@ impl BytecodeCompiler {
 		result
 	}
 
+	/// Compiles a string of Lua source code to bytecode.
+	///
+	/// Like [`compile_string`](BytecodeCompiler::compile_string), the source code itself is used as the chunk name.
+	pub fn compile_str(&self, src: &str, strip_debug: bool) -> Result<Bytecode, LuaError> {
+		self.compile_bytes(src, strip_debug)
+	}
+
+	/// Compiles Lua source code to bytecode.
+	///
+	/// The source code is loaded with `luaL_loadbuffer`, so unlike [`compile_string`](BytecodeCompiler::compile_string) it may contain NUL bytes and doesn't need to be valid UTF-8.
+	///
+	/// Like `loadstring`, the source code (up to the first NUL byte) is used as the chunk name.
+	pub fn compile_bytes<S: AsRef<[u8]>>(&self, src: S, strip_debug: bool) -> Result<Bytecode, LuaError> {
+		let src = src.as_ref();
+		let chunk_name = CString::new(src.split(|byte| *byte == 0).next().unwrap_or_default()).unwrap();
+		self.compile_buffer(src, &chunk_name, strip_debug)
+	}
+
+	/// Compiles Lua source code to bytecode, using the given chunk name.
+	///
+	/// The chunk name is embedded in unstripped bytecode and used in error messages and stack traces. Following Lua's conventions:
+	///
+	/// * `@addons/myaddon/lua/autorun/init.lua` is a file path, and shows up as `addons/myaddon/lua/autorun/init.lua:1:`. This is what Garry's Mod's own loader uses.
+	/// * `=name` is shown as-is, e.g. `name:1:`
+	/// * Anything else is treated as source code, e.g. `[string "print('Hello, world!')"]:1:`
+	///
+	/// ## Errors
+	/// Returns `LuaError::NulError` if the chunk name contains a NUL byte.
+	pub fn compile_named<S: AsRef<[u8]>>(&self, src: S, chunk_name: &str, strip_debug: bool) -> Result<Bytecode, LuaError> {
+		let chunk_name = CString::new(chunk_name)?;
+		self.compile_buffer(src.as_ref(), &chunk_name, strip_debug)
+	}
+
+	fn compile_buffer(&self, src: &[u8], chunk_name: &CStr, strip_debug: bool) -> Result<Bytecode, LuaError> {
+		let lua_state = self.lock()?;
+		unsafe {
+			self.load_buffer(*lua_state, src, chunk_name.as_ptr())?;
+			self.compile(*lua_state, strip_debug)
+		}
+	}
+
+	/// Loads a Lua source code file from its path and compiles it to bytecode.
+	///
+	/// The file is loaded with `luaL_loadfile`, so the chunk name is `@` followed by the path.
+	pub fn compile_path<P: AsRef<Path>>(&self, path: P, strip_debug: bool) -> Result<Bytecode, LuaError> {
+		let path = path_to_cstring(path.as_ref())?;
+
+		let lua_state = self.lock()?;
+		unsafe {
+			self.load_file(*lua_state, path.as_ptr())?;
+			self.compile(*lua_state, strip_debug)
+		}
+	}
+
 	/// Loads a string of Lua source code into the Lua state and compiles it to bytecode.
 	///
 	/// This function takes a `LuaString` (basically just a `*const char` in C) - you can use the `gluac::lua_string!()` macro to create one.
+	///
+	/// Prefer [`compile_str`](BytecodeCompiler::compile_str) or [`compile_bytes`](BytecodeCompiler::compile_bytes), which don't require a raw pointer.
+	#[allow(clippy::not_unsafe_ptr_arg_deref)]
 	pub fn compile_string(&self, src: LuaString, strip_debug: bool) -> Result<Bytecode, LuaError> {
 		let lua_state = self.lock()?;
 		unsafe {
-			lua_state.load_string(src)?;
+			self.load_string(*lua_state, src)?;
 			self.compile(*lua_state, strip_debug)
 		}
 	}
@@ -77,14 +311,97 @@ impl BytecodeCompiler {
 	/// Loads a file from its path into the Lua state and compiles it to bytecode.
 	///
 	/// This function takes a `LuaString` (basically just a `*const char` in C) - you can use the `gluac::lua_string!()` macro to create one.
+	///
+	/// Prefer [`compile_path`](BytecodeCompiler::compile_path), which doesn't require a raw pointer.
+	#[allow(clippy::not_unsafe_ptr_arg_deref)]
 	pub fn compile_file(&self, path: LuaString, strip_debug: bool) -> Result<Bytecode, LuaError> {
 		let lua_state = self.lock()?;
 		unsafe {
-			lua_state.load_file(path)?;
+			self.load_file(*lua_state, path)?;
 			self.compile(*lua_state, strip_debug)
 		}
 	}
 
+	/// Checks that a string of Lua source code has no syntax errors, without compiling it to bytecode.
+	///
+	/// Like [`compile_str`](BytecodeCompiler::compile_str), the source code itself is used as the chunk name.
+	pub fn check_str(&self, src: &str) -> Result<(), LuaError> {
+		self.check_bytes(src)
+	}
+
+	/// Checks that Lua source code has no syntax errors, without compiling it to bytecode.
+	///
+	/// Like [`compile_bytes`](BytecodeCompiler::compile_bytes), the source code (up to the first NUL byte) is used as the chunk name.
+	pub fn check_bytes<S: AsRef<[u8]>>(&self, src: S) -> Result<(), LuaError> {
+		let src = src.as_ref();
+		let chunk_name = CString::new(src.split(|byte| *byte == 0).next().unwrap_or_default()).unwrap();
+		self.check_buffer(src, &chunk_name)
+	}
+
+	/// Checks that Lua source code has no syntax errors, without compiling it to bytecode, using the given chunk name in any error.
+	///
+	/// See [`compile_named`](BytecodeCompiler::compile_named) for how chunk names are displayed.
+	///
+	/// ## Errors
+	/// Returns `LuaError::NulError` if the chunk name contains a NUL byte.
+	pub fn check_named<S: AsRef<[u8]>>(&self, src: S, chunk_name: &str) -> Result<(), LuaError> {
+		let chunk_name = CString::new(chunk_name)?;
+		self.check_buffer(src.as_ref(), &chunk_name)
+	}
+
+	fn check_buffer(&self, src: &[u8], chunk_name: &CStr) -> Result<(), LuaError> {
+		let lua_state = self.lock()?;
+		unsafe {
+			self.load_buffer(*lua_state, src, chunk_name.as_ptr())?;
+			lua_state.remove(-1); // Pop the loaded function off the stack, it isn't needed
+		}
+		Ok(())
+	}
+
+	/// Checks that a Lua source code file has no syntax errors, without compiling it to bytecode.
+	///
+	/// The file is loaded with `luaL_loadfile`, so the chunk name is `@` followed by the path.
+	pub fn check_path<P: AsRef<Path>>(&self, path: P) -> Result<(), LuaError> {
+		let path = path_to_cstring(path.as_ref())?;
+
+		let lua_state = self.lock()?;
+		unsafe {
+			self.load_file(*lua_state, path.as_ptr())?;
+			lua_state.remove(-1); // Pop the loaded function off the stack, it isn't needed
+		}
+		Ok(())
+	}
+
+	/// Checks that a string of Lua source code has no syntax errors, without compiling it to bytecode.
+	///
+	/// This function takes a `LuaString` (basically just a `*const char` in C) - you can use the `gluac::lua_string!()` macro to create one.
+	///
+	/// Prefer [`check_str`](BytecodeCompiler::check_str) or [`check_bytes`](BytecodeCompiler::check_bytes), which don't require a raw pointer.
+	#[allow(clippy::not_unsafe_ptr_arg_deref)]
+	pub fn check_string(&self, src: LuaString) -> Result<(), LuaError> {
+		let lua_state = self.lock()?;
+		unsafe {
+			self.load_string(*lua_state, src)?;
+			lua_state.remove(-1); // Pop the loaded function off the stack, it isn't needed
+		}
+		Ok(())
+	}
+
+	/// Checks that a file has no syntax errors, without compiling it to bytecode.
+	///
+	/// This function takes a `LuaString` (basically just a `*const char` in C) - you can use the `gluac::lua_string!()` macro to create one.
+	///
+	/// Prefer [`check_path`](BytecodeCompiler::check_path), which doesn't require a raw pointer.
+	#[allow(clippy::not_unsafe_ptr_arg_deref)]
+	pub fn check_file(&self, path: LuaString) -> Result<(), LuaError> {
+		let lua_state = self.lock()?;
+		unsafe {
+			self.load_file(*lua_state, path)?;
+			lua_state.remove(-1); // Pop the loaded function off the stack, it isn't needed
+		}
+		Ok(())
+	}
+
 	#[cfg(test)]
 	pub(crate) fn stack_size(&self) -> crate::lua::LuaInt {
 		let lua_state = self.lock().unwrap();
@@ -108,3 +425,37 @@ impl std::ops::Drop for BytecodeCompiler {
 		}
 	}
 }
+
+/// The OS's description of an I/O error (what `strerror` gives), which is what `luaL_loadfile` puts in its error messages.
+fn os_error_message(error: &std::io::Error) -> String {
+	let mut message = error.to_string();
+	if let Some(code) = error.raw_os_error() {
+		let suffix = format!(" (os error {})", code);
+		if message.ends_with(&suffix) {
+			message.truncate(message.len() - suffix.len());
+		}
+	}
+	message
+}
+
+#[cfg(unix)]
+fn cstr_to_path(path: &CStr) -> PathBuf {
+	use std::os::unix::ffi::OsStrExt;
+	PathBuf::from(std::ffi::OsStr::from_bytes(path.to_bytes()))
+}
+
+#[cfg(not(unix))]
+fn cstr_to_path(path: &CStr) -> PathBuf {
+	PathBuf::from(path.to_string_lossy().into_owned())
+}
+
+#[cfg(unix)]
+fn path_to_cstring(path: &Path) -> Result<CString, LuaError> {
+	use std::os::unix::ffi::OsStrExt;
+	Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+#[cfg(not(unix))]
+fn path_to_cstring(path: &Path) -> Result<CString, LuaError> {
+	Ok(CString::new(path.to_string_lossy().into_owned())?)
+}
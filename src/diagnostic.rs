@@ -0,0 +1,64 @@
+/// A syntax error reported by Lua, parsed from its `chunk:line: message near 'token'` format.
+///
+/// ```
+/// let diagnostic = gluac_rs::Diagnostic::parse("addons/myaddon/lua/autorun/init.lua:2: unexpected symbol near '<eof>'").unwrap();
+/// assert_eq!(diagnostic.chunk, "addons/myaddon/lua/autorun/init.lua");
+/// assert_eq!(diagnostic.line, 2);
+/// assert_eq!(diagnostic.message, "unexpected symbol");
+/// assert_eq!(diagnostic.near_token.as_deref(), Some("<eof>"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+	/// The chunk name as Lua displays it, e.g. `addons/myaddon/lua/autorun/init.lua` for the chunk name `@addons/myaddon/lua/autorun/init.lua`, or `[string "print("Hello, world!")"]` for source code loaded without a chunk name.
+	pub chunk: String,
+
+	/// The line the error was found on.
+	pub line: u32,
+
+	/// The error message, without the location and the token it was found near.
+	pub message: String,
+
+	/// The token the error was found near, e.g. `end` or `<eof>`.
+	pub near_token: Option<String>,
+}
+impl Diagnostic {
+	/// Parses a Lua error message of the form `chunk:line: message near 'token'`.
+	///
+	/// Returns `None` if the message doesn't start with a location, e.g. errors about files that couldn't be opened.
+	pub fn parse(error: &str) -> Option<Diagnostic> {
+		// The source code in [string "..."] chunk names can contain anything, so skip past it before looking for the line number
+		let search_from = if error.starts_with("[string \"") { error.find("\"]:")? + 2 } else { 0 };
+
+		let (chunk, line, rest) = error[search_from..].match_indices(':').find_map(|(colon, _)| {
+			let colon = search_from + colon;
+			let after = &error[colon + 1..];
+			let digits = after.bytes().take_while(u8::is_ascii_digit).count();
+			if digits == 0 || !after[digits..].starts_with(": ") {
+				return None;
+			}
+			let line = after[..digits].parse().ok()?;
+			Some((&error[..colon], line, &after[digits + 2..]))
+		})?;
+
+		let (message, near_token) = match rest.rfind(" near '") {
+			Some(near) if rest.ends_with('\'') && near + 7 < rest.len() => (&rest[..near], Some(rest[near + 7..rest.len() - 1].to_string())),
+			_ => (rest, None),
+		};
+
+		Some(Diagnostic {
+			chunk: chunk.to_string(),
+			line,
+			message: message.to_string(),
+			near_token,
+		})
+	}
+}
+impl std::fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}:{}: {}", self.chunk, self.line, self.message)?;
+		if let Some(near_token) = &self.near_token {
+			write!(f, " near '{}'", near_token)?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,348 @@
+//! Rewriting Garry's Mod's syntax extensions to Lua into standard Lua, for compiling GLua with a stock LuaJIT.
+//!
+//! Garry's Mod's `lua_shared` accepts:
+//!
+//! * `//` and `/* */` comments
+//! * `!=`, `&&`, `||` and `!` for `~=`, `and`, `or` and `not`
+//! * `continue` in loops
+//!
+//! These are rewritten without moving anything onto another line, so line numbers in errors and debug information stay the same.
+//! `continue` becomes a `goto` to a label at the end of the loop's body, which is wrapped in a `do ... end` block so that the label is
+//! never in the scope of the body's locals. In `repeat ... until` loops the label has to be right before `until` instead, as the condition
+//! can use the body's locals, so LuaJIT rejects a `continue` there if a local is declared after it.
+//!
+//! ```
+//! let lua = gluac_rs::glua::to_lua(b"if a != b && !c then return end // done");
+//! assert_eq!(&*lua, &b"if a ~= b and not c then return end -- done"[..]);
+//! ```
+
+use std::borrow::Cow;
+
+/// The prefix of the labels `continue` jumps to.
+const CONTINUE_LABEL: &str = "__glua_continue_";
+
+/// Rewrites GLua's syntax extensions in Lua source code into standard Lua.
+///
+/// Source code that doesn't use any of them, and precompiled bytecode, is returned as-is.
+/// Invalid source code is rewritten as far as possible, so that compiling it reports the same syntax error.
+pub fn to_lua(src: &[u8]) -> Cow<'_, [u8]> {
+	if src.starts_with(crate::bytecode::SIGNATURE) {
+		return Cow::Borrowed(src);
+	}
+
+	let tokens = Lexer { src, pos: 0 }.collect::<Vec<_>>();
+	let edits = edits(src, &tokens);
+	if edits.is_empty() {
+		return Cow::Borrowed(src);
+	}
+
+	let mut lua = Vec::with_capacity(src.len() + edits.len() * 8);
+	let mut pos = 0;
+	for edit in edits {
+		lua.extend_from_slice(&src[pos..edit.start]);
+		lua.extend_from_slice(edit.replacement.as_bytes());
+		pos = edit.end;
+	}
+	lua.extend_from_slice(&src[pos..]);
+	Cow::Owned(lua)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+	Name,
+	Symbol,
+	/// Strings, numbers and standard Lua comments, which are left alone.
+	Literal,
+	/// `//` comments
+	LineComment,
+	/// `/* */` comments
+	BlockComment,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token {
+	kind: TokenKind,
+	start: usize,
+	end: usize,
+}
+
+/// Splits source code into tokens, skipping whitespace.
+struct Lexer<'a> {
+	src: &'a [u8],
+	pos: usize,
+}
+impl Lexer<'_> {
+	fn peek(&self, offset: usize) -> Option<u8> {
+		self.src.get(self.pos + offset).copied()
+	}
+
+	/// Skips a long bracket (`[[ ]]`, `[==[ ]==]`) starting at the current position, returning whether there was one.
+	fn long_bracket(&mut self) -> bool {
+		let level = self.src[self.pos + 1..].iter().take_while(|byte| **byte == b'=').count();
+		if self.peek(1 + level) != Some(b'[') {
+			return false;
+		}
+
+		let mut close = Vec::with_capacity(level + 2);
+		close.push(b']');
+		close.resize(level + 1, b'=');
+		close.push(b']');
+
+		let body = self.pos + level + 2;
+		self.pos = match self.src[body..].windows(close.len()).position(|window| window == close) {
+			Some(offset) => body + offset + close.len(),
+			None => self.src.len(),
+		};
+		true
+	}
+
+	fn skip_while(&mut self, mut predicate: impl FnMut(u8) -> bool) {
+		while matches!(self.peek(0), Some(byte) if predicate(byte)) {
+			self.pos += 1;
+		}
+	}
+
+	fn number(&mut self) {
+		while let Some(byte) = self.peek(0) {
+			let exponent = matches!(byte, b'e' | b'E' | b'p' | b'P') && matches!(self.peek(1), Some(b'+' | b'-'));
+			if exponent {
+				self.pos += 2;
+			} else if byte.is_ascii_alphanumeric() || byte == b'.' || byte == b'_' {
+				self.pos += 1;
+			} else {
+				break;
+			}
+		}
+	}
+
+	fn quoted_string(&mut self, quote: u8) {
+		self.pos += 1;
+		while let Some(byte) = self.peek(0) {
+			match byte {
+				b'\\' => self.pos += 2,
+				b'\n' | b'\r' => return,
+				_ => {
+					self.pos += 1;
+					if byte == quote {
+						return;
+					}
+				}
+			}
+		}
+		self.pos = self.pos.min(self.src.len());
+	}
+}
+impl Iterator for Lexer<'_> {
+	type Item = Token;
+
+	fn next(&mut self) -> Option<Token> {
+		self.skip_while(|byte| byte.is_ascii_whitespace());
+
+		let start = self.pos;
+		let byte = self.peek(0)?;
+		let kind = match byte {
+			b'-' if self.peek(1) == Some(b'-') => {
+				self.pos += 2;
+				if !(self.peek(0) == Some(b'[') && self.long_bracket()) {
+					self.skip_while(|byte| byte != b'\n' && byte != b'\r');
+				}
+				TokenKind::Literal
+			}
+			b'/' if self.peek(1) == Some(b'/') => {
+				self.skip_while(|byte| byte != b'\n' && byte != b'\r');
+				TokenKind::LineComment
+			}
+			b'/' if self.peek(1) == Some(b'*') => {
+				self.pos = match self.src[start + 2..].windows(2).position(|window| window == b"*/") {
+					Some(offset) => start + 2 + offset + 2,
+					None => self.src.len(),
+				};
+				TokenKind::BlockComment
+			}
+			b'[' if self.long_bracket() => TokenKind::Literal,
+			b'"' | b'\'' => {
+				self.quoted_string(byte);
+				TokenKind::Literal
+			}
+			b'0'..=b'9' => {
+				self.number();
+				TokenKind::Literal
+			}
+			b'.' if matches!(self.peek(1), Some(b'0'..=b'9')) => {
+				self.number();
+				TokenKind::Literal
+			}
+			b'a'..=b'z' | b'A'..=b'Z' | b'_' | 0x80..=0xFF => {
+				self.skip_while(|byte| byte.is_ascii_alphanumeric() || byte == b'_' || byte >= 0x80);
+				TokenKind::Name
+			}
+			_ => {
+				let len = match &self.src[start..] {
+					[b'.', b'.', b'.', ..] => 3,
+					[b'!' | b'~' | b'=' | b'<' | b'>', b'=', ..] | [b'&', b'&', ..] | [b'|', b'|', ..] | [b'.', b'.', ..] | [b':', b':', ..] => 2,
+					_ => 1,
+				};
+				self.pos += len;
+				TokenKind::Symbol
+			}
+		};
+
+		Some(Token { kind, start, end: self.pos })
+	}
+}
+
+/// A replacement of part of the source code.
+#[derive(Debug)]
+struct Edit {
+	start: usize,
+	end: usize,
+	replacement: Cow<'static, str>,
+}
+impl Edit {
+	fn insert(pos: usize, text: String) -> Self {
+		Edit {
+			start: pos,
+			end: pos,
+			replacement: text.into(),
+		}
+	}
+}
+
+/// A block that's open while walking through the tokens.
+#[derive(Debug)]
+enum Block {
+	Function,
+	/// `while` or `for`, up to its `do`
+	LoopHeader,
+	/// The body of a `while` or `for` loop, with the index of its `do` token and of the edits of its `continue`s
+	Loop {
+		do_token: usize,
+		continues: Vec<usize>,
+	},
+	/// The body of a `repeat` loop, with the index of the edits of its `continue`s
+	Repeat {
+		continues: Vec<usize>,
+	},
+	Other,
+}
+
+fn edits(src: &[u8], tokens: &[Token]) -> Vec<Edit> {
+	let mut edits = Vec::new();
+	let mut blocks = Vec::new();
+	let mut loops = 0;
+
+	for (index, token) in tokens.iter().enumerate() {
+		let text = &src[token.start..token.end];
+		match token.kind {
+			TokenKind::LineComment => {
+				// Make sure the comment doesn't become a long comment
+				let replacement = if text.get(2) == Some(&b'[') { "-- " } else { "--" };
+				edits.push(Edit {
+					start: token.start,
+					end: token.start + 2,
+					replacement: replacement.into(),
+				});
+			}
+			TokenKind::BlockComment => {
+				// Keep the newlines in the comment so that the following lines don't move
+				let newlines = text.iter().filter(|byte| **byte == b'\n' || **byte == b'\r').map(|byte| *byte as char);
+				let replacement = std::iter::once(' ').chain(newlines).collect::<String>();
+				edits.push(Edit {
+					start: token.start,
+					end: token.end,
+					replacement: replacement.into(),
+				});
+			}
+			TokenKind::Symbol => {
+				let replacement = match text {
+					b"!=" => "~=".into(),
+					b"!" => keyword(src, token, "not"),
+					b"&&" => keyword(src, token, "and"),
+					b"||" => keyword(src, token, "or"),
+					_ => continue,
+				};
+				edits.push(Edit {
+					start: token.start,
+					end: token.end,
+					replacement,
+				});
+			}
+			TokenKind::Literal => {}
+			TokenKind::Name => {
+				let previous = index.checked_sub(1).map(|previous| &src[tokens[previous].start..tokens[previous].end]);
+				let is_field = matches!(previous, Some(b"." | b":" | b"goto"));
+				if is_field {
+					continue;
+				}
+
+				match text {
+					b"function" => blocks.push(Block::Function),
+					b"while" | b"for" => blocks.push(Block::LoopHeader),
+					b"repeat" => blocks.push(Block::Repeat { continues: Vec::new() }),
+					b"if" => blocks.push(Block::Other),
+					b"do" => match blocks.last_mut() {
+						Some(header @ Block::LoopHeader) => {
+							*header = Block::Loop {
+								do_token: index,
+								continues: Vec::new(),
+							}
+						}
+						_ => blocks.push(Block::Other),
+					},
+					b"end" => match blocks.pop() {
+						Some(Block::Loop { do_token, continues }) if !continues.is_empty() => {
+							loops += 1;
+							label(&mut edits, &continues, loops);
+							edits.push(Edit::insert(tokens[do_token].end, " do".to_string()));
+							edits.push(Edit::insert(token.start, format!("end ::{}{}:: ", CONTINUE_LABEL, loops)));
+						}
+						_ => {}
+					},
+					b"until" => match blocks.pop() {
+						Some(Block::Repeat { continues }) if !continues.is_empty() => {
+							loops += 1;
+							label(&mut edits, &continues, loops);
+							edits.push(Edit::insert(token.start, format!("::{}{}:: ", CONTINUE_LABEL, loops)));
+						}
+						_ => {}
+					},
+					b"continue" => {
+						let innermost_loop = blocks.iter_mut().rev().find(|block| !matches!(block, Block::Other | Block::LoopHeader));
+						if let Some(Block::Loop { continues, .. } | Block::Repeat { continues }) = innermost_loop {
+							// The label is numbered at the end of the loop
+							continues.push(edits.len());
+							edits.push(Edit::insert(token.start, String::new()));
+						}
+						// Otherwise leave it alone, so that compiling it reports a syntax error
+					}
+					_ => {}
+				}
+			}
+		}
+	}
+
+	// Insertions go before any replacement at the same position
+	edits.sort_by_key(|edit| (edit.start, edit.end));
+	edits
+}
+
+/// Replaces an operator with a keyword, adding spaces around it if it would otherwise run into the tokens next to it.
+fn keyword(src: &[u8], token: &Token, keyword: &'static str) -> Cow<'static, str> {
+	let is_word = |byte: Option<&u8>| matches!(byte, Some(byte) if byte.is_ascii_alphanumeric() || *byte == b'_' || *byte >= 0x80);
+	let before = is_word(token.start.checked_sub(1).and_then(|before| src.get(before)));
+	// An operator right after this one becomes a keyword too, so only the first of the two needs a space between them
+	let after = is_word(src.get(token.end)) || matches!(src.get(token.end), Some(b'!' | b'&' | b'|'));
+	match (before, after) {
+		(false, false) => keyword.into(),
+		(before, after) => format!("{}{}{}", if before { " " } else { "" }, keyword, if after { " " } else { "" }).into(),
+	}
+}
+
+/// Replaces a loop's `continue`s with a `goto` to its label.
+fn label(edits: &mut [Edit], continues: &[usize], label: usize) {
+	for continue_edit in continues {
+		let edit = &mut edits[*continue_edit];
+		edit.end = edit.start + "continue".len();
+		edit.replacement = format!("goto {}{}", CONTINUE_LABEL, label).into();
+	}
+}
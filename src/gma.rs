@@ -0,0 +1,324 @@
+//! Reading and writing Garry's Mod addon archives (`.gma`), as created by `gmad` and uploaded to the Steam Workshop.
+//!
+//! ```no_run
+//! use gluac_rs::gma::{Gma, GmaFile};
+//!
+//! let compiler = gluac_rs::compiler().unwrap();
+//!
+//! let mut gma = Gma::new("My Addon");
+//! gma.addon_type = "tool".to_string();
+//! gma.files.push(GmaFile::new("lua/autorun/init.lua", compiler.compile_path("lua/autorun/init.lua", true).unwrap()));
+//! std::fs::write("my_addon.gma", gma.write().unwrap()).unwrap();
+//!
+//! let gma = Gma::read(&std::fs::read("my_addon.gma").unwrap()).unwrap();
+//! assert_eq!(gma.files[0].path, "lua/autorun/init.lua");
+//! ```
+
+use std::{
+	convert::TryFrom,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The first bytes of every addon archive.
+pub const IDENT: &[u8; 4] = b"GMAD";
+
+/// The format version written by this module and by `gmad`. Versions 1 to 3 can be read.
+pub const VERSION: u8 = 3;
+
+/// The author `gmad` writes, as Garry's Mod shows the Workshop uploader instead.
+const DEFAULT_AUTHOR: &str = "Author Name";
+
+/// A Garry's Mod addon archive.
+///
+/// Strings that aren't valid UTF-8 are read lossily.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gma {
+	/// The SteamID64 of the addon's author. `gmad` leaves this as 0.
+	pub steam_id: u64,
+
+	/// When the archive was created, in seconds since the Unix epoch.
+	pub timestamp: u64,
+
+	/// Other content the addon requires. `gmad` leaves this empty.
+	pub required_content: Vec<String>,
+
+	/// The addon's title.
+	pub name: String,
+
+	pub description: String,
+
+	/// The addon's type, such as `gamemode`, `map`, `weapon`, `vehicle`, `npc`, `entity`, `tool`, `effects`, `model` or `servercontent`.
+	pub addon_type: String,
+
+	/// The addon's Workshop tags, such as `fun` or `roleplay`.
+	pub tags: Vec<String>,
+
+	pub author: String,
+
+	/// The addon's version. `gmad` always writes 1.
+	pub version: i32,
+
+	pub files: Vec<GmaFile>,
+}
+impl Gma {
+	/// Creates an empty archive, timestamped with the current time.
+	pub fn new<S: Into<String>>(name: S) -> Self {
+		Self {
+			steam_id: 0,
+			timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or(0),
+			required_content: Vec::new(),
+			name: name.into(),
+			description: String::new(),
+			addon_type: String::new(),
+			tags: Vec::new(),
+			author: DEFAULT_AUTHOR.to_string(),
+			version: 1,
+			files: Vec::new(),
+		}
+	}
+
+	/// Reads an archive, checking each file's CRC32.
+	pub fn read(data: &[u8]) -> Result<Gma, GmaError> {
+		let mut reader = Reader { data, pos: 0 };
+
+		if reader.bytes(IDENT.len())? != IDENT {
+			return Err(GmaError::InvalidIdent);
+		}
+
+		let format_version = reader.u8()?;
+		if !(1..=VERSION).contains(&format_version) {
+			return Err(GmaError::UnsupportedVersion(format_version));
+		}
+
+		let steam_id = reader.u64()?;
+		let timestamp = reader.u64()?;
+
+		let mut required_content = Vec::new();
+		if format_version > 1 {
+			loop {
+				let content = reader.string()?;
+				if content.is_empty() {
+					break;
+				}
+				required_content.push(content);
+			}
+		}
+
+		let name = reader.string()?;
+		let (description, addon_type, tags) = parse_description(reader.string()?);
+		let author = reader.string()?;
+		let version = reader.u32()? as i32;
+
+		let mut entries = Vec::new();
+		while reader.u32()? != 0 {
+			let path = reader.string()?;
+			let size = reader.u64()?;
+			let crc = reader.u32()?;
+			entries.push((path, size, crc));
+		}
+
+		let mut files = Vec::with_capacity(entries.len());
+		for (path, size, crc) in entries {
+			let offset = reader.pos;
+			let data = usize::try_from(size)
+				.map_err(|_| GmaError::UnexpectedEof)
+				.and_then(|size| reader.bytes(size))?;
+			if crc32fast::hash(data) != crc {
+				return Err(GmaError::CrcMismatch { path, offset });
+			}
+			files.push(GmaFile { path, data: data.to_vec() });
+		}
+
+		// The archive ends with a CRC32 of everything before it, which Garry's Mod doesn't check either
+		Ok(Gma {
+			steam_id,
+			timestamp,
+			required_content,
+			name,
+			description,
+			addon_type,
+			tags,
+			author,
+			version,
+			files,
+		})
+	}
+
+	/// Serializes the archive.
+	///
+	/// Garry's Mod expects file paths to be lowercase and separated by `/`, like `lua/autorun/init.lua`.
+	pub fn write(&self) -> Result<Vec<u8>, GmaError> {
+		let mut writer = Writer(Vec::with_capacity(
+			self.files.iter().map(|file| file.data.len() + file.path.len() + 17).sum::<usize>() + 256,
+		));
+
+		writer.0.extend_from_slice(IDENT);
+		writer.0.push(VERSION);
+		writer.0.extend_from_slice(&self.steam_id.to_le_bytes());
+		writer.0.extend_from_slice(&self.timestamp.to_le_bytes());
+
+		for content in &self.required_content {
+			if content.is_empty() {
+				return Err(GmaError::InvalidString(content.clone()));
+			}
+			writer.string(content)?;
+		}
+		writer.string("")?;
+
+		writer.string(&self.name)?;
+		writer.string(&self.metadata_json())?;
+		writer.string(&self.author)?;
+		writer.0.extend_from_slice(&self.version.to_le_bytes());
+
+		for (number, file) in self.files.iter().enumerate() {
+			writer.0.extend_from_slice(&(number as u32 + 1).to_le_bytes());
+			writer.string(&file.path)?;
+			writer.0.extend_from_slice(&(file.data.len() as u64).to_le_bytes());
+			writer.0.extend_from_slice(&crc32fast::hash(&file.data).to_le_bytes());
+		}
+		writer.0.extend_from_slice(&0u32.to_le_bytes());
+
+		for file in &self.files {
+			writer.0.extend_from_slice(&file.data);
+		}
+
+		let crc = crc32fast::hash(&writer.0);
+		writer.0.extend_from_slice(&crc.to_le_bytes());
+		Ok(writer.0)
+	}
+
+	/// The description, type and tags as the JSON stored in the archive's description.
+	fn metadata_json(&self) -> String {
+		serde_json::json!({
+			"description": self.description,
+			"type": self.addon_type,
+			"tags": self.tags,
+		})
+		.to_string()
+	}
+
+	/// Returns the file at a path.
+	pub fn file(&self, path: &str) -> Option<&GmaFile> {
+		self.files.iter().find(|file| file.path == path)
+	}
+}
+
+/// A file in an addon archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GmaFile {
+	/// The file's path, relative to the game's `garrysmod` directory.
+	pub path: String,
+	pub data: Vec<u8>,
+}
+impl GmaFile {
+	pub fn new<S: Into<String>, D: Into<Vec<u8>>>(path: S, data: D) -> Self {
+		Self {
+			path: path.into(),
+			data: data.into(),
+		}
+	}
+}
+
+/// Splits the archive's description into the addon's description, type and tags.
+///
+/// `gmad` stores these as JSON, but archives made by other tools may just contain the description.
+fn parse_description(description: String) -> (String, String, Vec<String>) {
+	let json = match serde_json::from_str::<serde_json::Value>(&description) {
+		Ok(serde_json::Value::Object(json)) => json,
+		_ => return (description, String::new(), Vec::new()),
+	};
+
+	let string = |key| json.get(key).and_then(serde_json::Value::as_str).unwrap_or_default().to_string();
+	let tags = json
+		.get("tags")
+		.and_then(serde_json::Value::as_array)
+		.map(|tags| tags.iter().filter_map(serde_json::Value::as_str).map(str::to_string).collect())
+		.unwrap_or_default();
+
+	(string("description"), string("type"), tags)
+}
+
+struct Reader<'a> {
+	data: &'a [u8],
+	pos: usize,
+}
+impl<'a> Reader<'a> {
+	fn bytes(&mut self, len: usize) -> Result<&'a [u8], GmaError> {
+		let end = self
+			.pos
+			.checked_add(len)
+			.filter(|end| *end <= self.data.len())
+			.ok_or(GmaError::UnexpectedEof)?;
+		let bytes = &self.data[self.pos..end];
+		self.pos = end;
+		Ok(bytes)
+	}
+
+	fn u8(&mut self) -> Result<u8, GmaError> {
+		Ok(self.bytes(1)?[0])
+	}
+
+	fn u32(&mut self) -> Result<u32, GmaError> {
+		let mut bytes = [0; 4];
+		bytes.copy_from_slice(self.bytes(4)?);
+		Ok(u32::from_le_bytes(bytes))
+	}
+
+	fn u64(&mut self) -> Result<u64, GmaError> {
+		let mut bytes = [0; 8];
+		bytes.copy_from_slice(self.bytes(8)?);
+		Ok(u64::from_le_bytes(bytes))
+	}
+
+	/// Reads a NUL-terminated string.
+	fn string(&mut self) -> Result<String, GmaError> {
+		let len = self.data[self.pos..].iter().position(|byte| *byte == 0).ok_or(GmaError::UnexpectedEof)?;
+		let string = String::from_utf8_lossy(self.bytes(len)?).into_owned();
+		self.pos += 1;
+		Ok(string)
+	}
+}
+
+struct Writer(Vec<u8>);
+impl Writer {
+	/// Writes a NUL-terminated string.
+	fn string(&mut self, string: &str) -> Result<(), GmaError> {
+		if string.contains('\0') {
+			return Err(GmaError::InvalidString(string.to_string()));
+		}
+		self.0.extend_from_slice(string.as_bytes());
+		self.0.push(0);
+		Ok(())
+	}
+}
+
+/// An error encountered while reading or writing an addon archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GmaError {
+	/// The data doesn't start with `GMAD`
+	InvalidIdent,
+
+	/// The format version isn't one we know how to read.
+	UnsupportedVersion(u8),
+
+	/// The data ended in the middle of the archive.
+	UnexpectedEof,
+
+	/// A file's contents don't match the CRC32 in the file table.
+	CrcMismatch { path: String, offset: usize },
+
+	/// A string can't be written, as it contains a NUL byte, or is an empty entry in `required_content`.
+	InvalidString(String),
+}
+impl std::fmt::Display for GmaError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			GmaError::InvalidIdent => write!(f, "not a Garry's Mod addon archive"),
+			GmaError::UnsupportedVersion(version) => write!(f, "unsupported addon archive version {}", version),
+			GmaError::UnexpectedEof => write!(f, "unexpected end of addon archive"),
+			GmaError::CrcMismatch { path, offset } => write!(f, "{} at offset {} doesn't match its CRC32", path, offset),
+			GmaError::InvalidString(string) => write!(f, "{:?} can't be written to an addon archive", string),
+		}
+	}
+}
+impl std::error::Error for GmaError {}
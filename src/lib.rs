@@ -16,11 +16,36 @@ pub(crate) type MutexGuard<'a, T> = std::sync::MutexGuard<'a, T>;
 
 pub mod lua;
 
+pub mod backend;
+
+pub mod bytecode;
+
+pub mod glua;
+
+pub mod gma;
+
+pub mod lua_cache;
+
+pub mod realm;
+
 #[macro_use]
 mod api;
 pub use api::*;
 
 mod compiler;
+pub use compiler::{BytecodeCompiler, CompilerBuilder};
+
+mod pool;
+pub use pool::{CompilerPool, PooledCompiler};
+
+mod cache;
+pub use cache::CompileCache;
+
+mod diagnostic;
+pub use diagnostic::Diagnostic;
+
+mod library;
+pub use library::LUA_SHARED_ENV;
 
 #[cfg(test)]
 mod tests;
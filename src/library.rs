@@ -0,0 +1,333 @@
+use std::{
+	ffi::c_void,
+	fs::File,
+	io::{Read, Seek, SeekFrom},
+	os::raw::c_int,
+	path::{Path, PathBuf},
+};
+
+use libloading::Library;
+
+use crate::{LoadAttempt, LoadError};
+
+#[cfg(target_os = "windows")]
+pub(crate) const LIBRARY_NAMES: &[&str] = &["lua_shared.dll"];
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) const LIBRARY_NAMES: &[&str] = &["lua_shared_srv.so", "lua_shared.so"];
+
+#[cfg(target_pointer_width = "32")]
+pub(crate) const TARGET_BITNESS: usize = 32;
+
+#[cfg(target_pointer_width = "64")]
+pub(crate) const TARGET_BITNESS: usize = 64;
+
+/// Environment variable containing extra paths (separated like `PATH`) to `lua_shared` or directories containing it.
+pub const LUA_SHARED_ENV: &str = "GLUAC_LUA_SHARED";
+
+/// Libraries `lua_shared` links to, which are loaded from the same directory first so the dynamic linker doesn't need to find them itself.
+#[cfg(target_os = "windows")]
+const DEPENDENCY_NAMES: &[&str] = &["tier0.dll", "vstdlib.dll"];
+
+#[cfg(not(target_os = "windows"))]
+const DEPENDENCY_NAMES: &[&str] = &["libtier0_srv.so", "libtier0.so", "libvstdlib_srv.so", "libvstdlib.so", "libsteam_api.so"];
+
+/// Directories in a Garry's Mod installation that contain `lua_shared` for this target.
+#[cfg(all(target_os = "windows", target_pointer_width = "64"))]
+const GMOD_BIN_DIRS: &[&str] = &["bin/win64"];
+
+#[cfg(all(target_os = "windows", target_pointer_width = "32"))]
+const GMOD_BIN_DIRS: &[&str] = &["garrysmod/bin", "bin"];
+
+#[cfg(all(not(target_os = "windows"), target_pointer_width = "64"))]
+const GMOD_BIN_DIRS: &[&str] = &["bin/linux64"];
+
+#[cfg(all(not(target_os = "windows"), target_pointer_width = "32"))]
+const GMOD_BIN_DIRS: &[&str] = &["bin/linux32", "garrysmod/bin", "bin"];
+
+/// Garry's Mod's client and dedicated server installation directories in `steamapps/common`.
+const GMOD_APP_DIRS: &[&str] = &["GarrysMod", "GarrysModDS"];
+
+#[derive(Debug, Clone)]
+pub(crate) struct LibrarySearch {
+	pub(crate) library_paths: Vec<PathBuf>,
+	pub(crate) gmod_paths: Vec<PathBuf>,
+	pub(crate) env: bool,
+	pub(crate) auto_probe: bool,
+}
+impl Default for LibrarySearch {
+	fn default() -> Self {
+		Self {
+			library_paths: Vec::new(),
+			gmod_paths: Vec::new(),
+			env: true,
+			auto_probe: true,
+		}
+	}
+}
+impl LibrarySearch {
+	/// Returns every path `lua_shared` should be loaded from, in order of preference.
+	pub(crate) fn candidates(&self) -> Vec<PathBuf> {
+		let mut candidates = Vec::new();
+
+		for path in &self.library_paths {
+			push_candidates(&mut candidates, path, LIBRARY_NAMES);
+		}
+
+		if self.env {
+			if let Some(paths) = std::env::var_os(LUA_SHARED_ENV) {
+				for path in std::env::split_paths(&paths).filter(|path| !path.as_os_str().is_empty()) {
+					push_candidates(&mut candidates, &path, LIBRARY_NAMES);
+				}
+			}
+		}
+
+		if let Some(exe_dir) = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+			candidates.extend(LIBRARY_NAMES.iter().map(|name| exe_dir.join(name)).filter(|path| path.is_file()));
+		}
+
+		// Let the dynamic linker search for it
+		candidates.extend(LIBRARY_NAMES.iter().map(PathBuf::from));
+
+		let mut gmod_paths = self.gmod_paths.clone();
+		if self.auto_probe {
+			gmod_paths.extend(steam_gmod_installs());
+		}
+		for gmod_path in gmod_paths {
+			for bin_dir in GMOD_BIN_DIRS {
+				let bin_dir = gmod_path.join(bin_dir);
+				candidates.extend(LIBRARY_NAMES.iter().map(|name| bin_dir.join(name)).filter(|path| path.is_file()));
+			}
+		}
+
+		let mut deduped = Vec::with_capacity(candidates.len());
+		for candidate in candidates {
+			if !deduped.contains(&candidate) {
+				deduped.push(candidate);
+			}
+		}
+		deduped
+	}
+}
+
+/// Adds a path to a library to the candidates, or the path of each of the library's names if it's a directory.
+pub(crate) fn push_candidates(candidates: &mut Vec<PathBuf>, path: &Path, names: &[&str]) {
+	if path.is_dir() {
+		candidates.extend(names.iter().map(|name| path.join(name)));
+	} else {
+		candidates.push(path.to_path_buf());
+	}
+}
+
+/// Loads a library, first loading any of `lua_shared`'s dependencies that are next to it.
+pub(crate) unsafe fn load_library(path: &Path) -> Result<Library, libloading::Error> {
+	if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+		for dependency in DEPENDENCY_NAMES.iter().map(|name| dir.join(name)).filter(|path| path.is_file()) {
+			if let Ok(dependency) = Library::new(dependency) {
+				std::mem::forget(dependency); // Keep this library referenced forever
+			}
+		}
+	}
+	Library::new(path)
+}
+
+/// Creates the error for when none of the candidates could be loaded.
+pub(crate) fn not_found(tried: Vec<LoadAttempt>) -> LoadError {
+	// The dynamic linker's error messages for a library of the wrong pointer width are rather cryptic, so check whether that's the reason ourselves
+	let wrong_bitness = tried.iter().find_map(|attempt| match library_bitness(&attempt.path) {
+		Some(bitness) if bitness != TARGET_BITNESS => Some((attempt.path.clone(), bitness)),
+		_ => None,
+	});
+
+	match wrong_bitness {
+		Some((library, bitness)) => LoadError::WrongBitness { library, bitness, tried },
+		None => LoadError::LibraryNotFound { tried },
+	}
+}
+
+/// Finds Garry's Mod client and dedicated server installations in Steam library folders.
+fn steam_gmod_installs() -> Vec<PathBuf> {
+	let mut libraries: Vec<PathBuf> = Vec::new();
+	for root in steam_roots() {
+		let root = match root.canonicalize() {
+			Ok(root) => root,
+			Err(_) => continue,
+		};
+
+		let library_folders = std::fs::read_to_string(root.join("steamapps/libraryfolders.vdf"))
+			.map(|vdf| parse_library_folders(&vdf))
+			.unwrap_or_default();
+
+		for library in std::iter::once(root).chain(library_folders) {
+			if !libraries.contains(&library) {
+				libraries.push(library);
+			}
+		}
+	}
+
+	libraries
+		.into_iter()
+		.flat_map(|library| GMOD_APP_DIRS.iter().map(move |app| library.join("steamapps/common").join(app)))
+		.filter(|install| install.is_dir())
+		.collect()
+}
+
+#[cfg(target_os = "windows")]
+fn steam_roots() -> Vec<PathBuf> {
+	["ProgramFiles(x86)", "ProgramFiles"]
+		.iter()
+		.filter_map(std::env::var_os)
+		.map(|program_files| PathBuf::from(program_files).join("Steam"))
+		.collect()
+}
+
+#[cfg(not(target_os = "windows"))]
+fn steam_roots() -> Vec<PathBuf> {
+	let home = match std::env::var_os("HOME") {
+		Some(home) => PathBuf::from(home),
+		None => return Vec::new(),
+	};
+
+	[
+		".steam/steam",
+		".steam/root",
+		".local/share/Steam",
+		".var/app/com.valvesoftware.Steam/.local/share/Steam",
+		"Steam",
+	]
+	.iter()
+	.map(|dir| home.join(dir))
+	.collect()
+}
+
+/// Extracts the library folder paths from Steam's `libraryfolders.vdf`.
+///
+/// Supports both the current format (`"path" "..."` inside each numbered block) and the legacy format (`"1" "..."`).
+pub(crate) fn parse_library_folders(vdf: &str) -> Vec<PathBuf> {
+	let mut paths = Vec::new();
+
+	for line in vdf.lines() {
+		let mut tokens = line.split('"').skip(1).step_by(2);
+		let (key, value) = match (tokens.next(), tokens.next()) {
+			(Some(key), Some(value)) => (key, value),
+			_ => continue,
+		};
+
+		let is_path = key.eq_ignore_ascii_case("path") || (key.bytes().all(|byte| byte.is_ascii_digit()) && value.contains(&['/', '\\'][..]));
+		if is_path {
+			paths.push(PathBuf::from(value.replace("\\\\", "\\")));
+		}
+	}
+
+	paths
+}
+
+/// Finds the file of the loaded library containing an address, such as one of its functions.
+#[cfg(unix)]
+pub(crate) fn loaded_file(address: *const c_void) -> Option<PathBuf> {
+	use std::{ffi::CStr, os::raw::c_char, os::unix::ffi::OsStrExt};
+
+	#[repr(C)]
+	struct DlInfo {
+		dli_fname: *const c_char,
+		dli_fbase: *mut c_void,
+		dli_sname: *const c_char,
+		dli_saddr: *mut c_void,
+	}
+
+	extern "C" {
+		fn dladdr(address: *const c_void, info: *mut DlInfo) -> c_int;
+	}
+
+	unsafe {
+		let mut info = std::mem::MaybeUninit::<DlInfo>::zeroed();
+		if dladdr(address, info.as_mut_ptr()) == 0 {
+			return None;
+		}
+
+		let info = info.assume_init();
+		if info.dli_fname.is_null() {
+			return None;
+		}
+
+		let file_name = CStr::from_ptr(info.dli_fname).to_bytes();
+		if file_name.is_empty() {
+			return None;
+		}
+		Some(PathBuf::from(std::ffi::OsStr::from_bytes(file_name)))
+	}
+}
+
+/// Finds the file of the loaded library containing an address, such as one of its functions.
+#[cfg(windows)]
+pub(crate) fn loaded_file(address: *const c_void) -> Option<PathBuf> {
+	use std::os::windows::ffi::OsStringExt;
+
+	const GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT: u32 = 0x2;
+	const GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS: u32 = 0x4;
+	const MAX_PATH_LEN: usize = 32768;
+
+	#[link(name = "kernel32")]
+	extern "system" {
+		fn GetModuleHandleExW(flags: u32, address: *const c_void, module: *mut *mut c_void) -> c_int;
+		fn GetModuleFileNameW(module: *mut c_void, file_name: *mut u16, size: u32) -> u32;
+	}
+
+	unsafe {
+		let mut module = std::ptr::null_mut();
+		if GetModuleHandleExW(
+			GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+			address,
+			&mut module,
+		) == 0
+		{
+			return None;
+		}
+
+		let mut file_name = vec![0u16; MAX_PATH_LEN];
+		let len = GetModuleFileNameW(module, file_name.as_mut_ptr(), MAX_PATH_LEN as u32) as usize;
+		if len == 0 || len >= MAX_PATH_LEN {
+			return None;
+		}
+		Some(PathBuf::from(std::ffi::OsString::from_wide(&file_name[..len])))
+	}
+}
+
+/// Reads the pointer width a shared library was built for from its ELF or PE header.
+///
+/// Returns `None` if the file doesn't exist or isn't a recognised shared library.
+pub(crate) fn library_bitness(path: &Path) -> Option<usize> {
+	let mut file = File::open(path).ok()?;
+
+	let mut header = [0u8; 64];
+	file.read_exact(&mut header).ok()?;
+
+	if header.starts_with(b"\x7FELF") {
+		// e_ident[EI_CLASS]
+		return match header[4] {
+			1 => Some(32),
+			2 => Some(64),
+			_ => None,
+		};
+	}
+
+	if header.starts_with(b"MZ") {
+		// e_lfanew points to the PE signature, which is followed by the COFF header's Machine field
+		let e_lfanew = u32::from_le_bytes([header[0x3C], header[0x3D], header[0x3E], header[0x3F]]);
+		file.seek(SeekFrom::Start(e_lfanew as u64)).ok()?;
+
+		let mut pe = [0u8; 6];
+		file.read_exact(&mut pe).ok()?;
+		if &pe[0..4] != b"PE\0\0" {
+			return None;
+		}
+
+		return match u16::from_le_bytes([pe[4], pe[5]]) {
+			0x014C => Some(32),          // IMAGE_FILE_MACHINE_I386
+			0x8664 | 0xAA64 => Some(64), // IMAGE_FILE_MACHINE_AMD64, IMAGE_FILE_MACHINE_ARM64
+			_ => None,
+		};
+	}
+
+	None
+}
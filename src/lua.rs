@@ -1,6 +1,8 @@
+use std::path::{Path, PathBuf};
+
 use libloading::{Library, Symbol};
 
-use crate::LuaError;
+use crate::{library, LoadAttempt, LoadError, LuaError};
 
 pub type LuaInt = std::os::raw::c_int;
 pub type LuaSize = usize;
@@ -39,181 +41,279 @@ impl LuaError {
 }
 
 lazy_static::lazy_static! {
-	static ref LUA_SHARED: LuaShared = LuaShared::import();
+	/// Every library that has been loaded so far and the candidate path it was loaded by, so that we only ever load and leak each library once.
+	static ref LOADED: std::sync::Mutex<Vec<(PathBuf, &'static LuaApi)>> = std::sync::Mutex::new(Vec::new());
 }
-struct LuaShared {
-	lual_newstate: Symbol<'static, unsafe extern "C" fn() -> LuaState>,
-	lual_openlibs: Symbol<'static, unsafe extern "C" fn(state: LuaState)>,
-	lual_loadfile: Symbol<'static, unsafe extern "C" fn(state: LuaState, path: LuaString) -> LuaInt>,
-	lual_loadstring: Symbol<'static, unsafe extern "C" fn(state: LuaState, path: LuaString) -> LuaInt>,
-	lua_getfield: Symbol<'static, unsafe extern "C" fn(state: LuaState, index: LuaInt, k: LuaString)>,
-	lua_pushvalue: Symbol<'static, unsafe extern "C" fn(state: LuaState, index: LuaInt)>,
-	lua_pushboolean: Symbol<'static, unsafe extern "C" fn(state: LuaState, bool: LuaInt)>,
-	lua_tolstring: Symbol<'static, unsafe extern "C" fn(state: LuaState, index: LuaInt, out_size: *mut LuaSize) -> LuaString>,
-	lua_pcall: Symbol<'static, unsafe extern "C" fn(state: LuaState, nargs: LuaInt, nresults: LuaInt, errfunc: LuaInt) -> LuaInt>,
-	lua_remove: Symbol<'static, unsafe extern "C" fn(state: LuaState, index: LuaInt)>,
-	lua_close: Symbol<'static, unsafe extern "C" fn(state: LuaState)>,
+
+type LuaStatePtr = *const std::ffi::c_void;
+
+/// The functions of the Lua C API a [`BytecodeCompiler`](crate::BytecodeCompiler) uses, from a loaded `lua_shared` or LuaJIT library.
+///
+/// These are returned by a [`Backend`](crate::backend::Backend), and live for the rest of the process once loaded.
+pub struct LuaApi {
+	path: PathBuf,
+
+	lual_newstate: unsafe extern "C" fn() -> LuaStatePtr,
+	lual_openlibs: unsafe extern "C" fn(state: LuaStatePtr),
+	lual_loadfile: unsafe extern "C" fn(state: LuaStatePtr, path: LuaString) -> LuaInt,
+	lual_loadstring: unsafe extern "C" fn(state: LuaStatePtr, path: LuaString) -> LuaInt,
+	lual_loadbuffer: unsafe extern "C" fn(state: LuaStatePtr, buff: LuaString, size: LuaSize, name: LuaString) -> LuaInt,
+	lua_getfield: unsafe extern "C" fn(state: LuaStatePtr, index: LuaInt, k: LuaString),
+	lua_pushvalue: unsafe extern "C" fn(state: LuaStatePtr, index: LuaInt),
+	lua_pushboolean: unsafe extern "C" fn(state: LuaStatePtr, bool: LuaInt),
+	lua_tolstring: unsafe extern "C" fn(state: LuaStatePtr, index: LuaInt, out_size: *mut LuaSize) -> LuaString,
+	lua_pcall: unsafe extern "C" fn(state: LuaStatePtr, nargs: LuaInt, nresults: LuaInt, errfunc: LuaInt) -> LuaInt,
+	lua_remove: unsafe extern "C" fn(state: LuaStatePtr, index: LuaInt),
+	lua_close: unsafe extern "C" fn(state: LuaStatePtr),
 
 	#[cfg(test)]
-	lua_gettop: Symbol<'static, unsafe extern "C" fn(state: LuaState) -> LuaInt>,
+	lua_gettop: unsafe extern "C" fn(state: LuaStatePtr) -> LuaInt,
 
 	#[cfg(test)]
-	lua_type: Symbol<'static, unsafe extern "C" fn(state: LuaState, index: LuaInt) -> LuaInt>,
+	lua_type: unsafe extern "C" fn(state: LuaStatePtr, index: LuaInt) -> LuaInt,
 
 	#[cfg(test)]
-	lua_typename: Symbol<'static, unsafe extern "C" fn(state: LuaState, lua_type_id: LuaInt) -> LuaString>,
+	lua_typename: unsafe extern "C" fn(state: LuaStatePtr, lua_type_id: LuaInt) -> LuaString,
 }
-impl LuaShared {
-	fn import() -> Self {
-		unsafe {
-			let library = Self::find_library();
-			let library = Box::leak(Box::new(library)); // Keep this library referenced forever
-
-			macro_rules! find_symbol {
-				( $symbol:literal ) => {
-					Self::find_symbol(library, concat!($symbol, "\0").as_bytes())
-				};
+impl LuaApi {
+	/// Loads the first of the given libraries that can be loaded, or returns the already loaded library if one of them was loaded before.
+	///
+	/// This is the building block of [`Backend`](crate::backend::Backend) implementations that load a library from disk.
+	///
+	/// ## Errors
+	/// Returns `LoadError::LibraryNotFound` (or `LoadError::WrongBitness`) with every path that was tried if none of them could be loaded.
+	/// Libraries that load but aren't Lua libraries (they're missing a symbol we need) are skipped and listed there too.
+	pub fn load<I: IntoIterator<Item = PathBuf>>(candidates: I) -> Result<&'static LuaApi, LoadError> {
+		let mut loaded = LOADED.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+
+		let mut tried = Vec::new();
+		for path in candidates {
+			if let Some((_, api)) = loaded.iter().find(|(candidate, _)| *candidate == path) {
+				return Ok(api);
 			}
 
-			Self {
-				lual_newstate: find_symbol!("luaL_newstate"),
-				lual_openlibs: find_symbol!("luaL_openlibs"),
-				lual_loadfile: find_symbol!("luaL_loadfile"),
-				lual_loadstring: find_symbol!("luaL_loadstring"),
-				lua_getfield: find_symbol!("lua_getfield"),
-				lua_pushvalue: find_symbol!("lua_pushvalue"),
-				lua_pushboolean: find_symbol!("lua_pushboolean"),
-				lua_tolstring: find_symbol!("lua_tolstring"),
-				lua_pcall: find_symbol!("lua_pcall"),
-				lua_remove: find_symbol!("lua_remove"),
-				lua_close: find_symbol!("lua_close"),
-
-				#[cfg(test)]
-				lua_gettop: find_symbol!("lua_gettop"),
-
-				#[cfg(test)]
-				lua_type: find_symbol!("lua_type"),
-
-				#[cfg(test)]
-				lua_typename: find_symbol!("lua_typename"),
+			let library = match unsafe { library::load_library(&path) } {
+				Ok(library) => library,
+				Err(error) => {
+					tried.push(LoadAttempt {
+						path,
+						error: error.to_string(),
+					});
+					continue;
+				}
+			};
+
+			// A library of the same name from another game or the wrong branch may come before the right one, so keep looking
+			match unsafe { Self::import(&library, &path) } {
+				Ok(mut api) => {
+					// The dynamic linker may have found a library given by name anywhere, so find out which file it really is
+					if let Some(file) = library::loaded_file(api.lual_newstate as *const std::ffi::c_void) {
+						api.path = file;
+					}
+
+					let api = match loaded.iter().find(|(_, loaded)| loaded.path == api.path) {
+						Some((_, loaded)) => *loaded,
+						None => {
+							std::mem::forget(library); // Keep this library referenced forever, as the API points into it
+							Box::leak(Box::new(api))
+						}
+					};
+					loaded.push((path, api));
+					return Ok(api);
+				}
+				Err(error) => tried.push(LoadAttempt {
+					path,
+					error: error.to_string(),
+				}),
 			}
 		}
+
+		Err(library::not_found(tried))
 	}
 
-	unsafe fn find_symbol<T>(library: &'static Library, name: &[u8]) -> Symbol<'static, T> {
-		match library.get(name) {
-			Ok(symbol) => symbol,
-			Err(err) => panic!("Failed to find symbol \"{}\"\n{:#?}", String::from_utf8_lossy(name), err),
+	/// Finds every function we need in a library. The library must be kept loaded for as long as the returned API is used.
+	unsafe fn import(library: &Library, path: &Path) -> Result<Self, LoadError> {
+		macro_rules! find_symbol {
+			( $symbol:literal ) => {
+				*Self::find_symbol(library, path, $symbol)?
+			};
 		}
+
+		Ok(Self {
+			lual_newstate: find_symbol!("luaL_newstate"),
+			lual_openlibs: find_symbol!("luaL_openlibs"),
+			lual_loadfile: find_symbol!("luaL_loadfile"),
+			lual_loadstring: find_symbol!("luaL_loadstring"),
+			lual_loadbuffer: find_symbol!("luaL_loadbuffer"),
+			lua_getfield: find_symbol!("lua_getfield"),
+			lua_pushvalue: find_symbol!("lua_pushvalue"),
+			lua_pushboolean: find_symbol!("lua_pushboolean"),
+			lua_tolstring: find_symbol!("lua_tolstring"),
+			lua_pcall: find_symbol!("lua_pcall"),
+			lua_remove: find_symbol!("lua_remove"),
+			lua_close: find_symbol!("lua_close"),
+
+			#[cfg(test)]
+			lua_gettop: find_symbol!("lua_gettop"),
+
+			#[cfg(test)]
+			lua_type: find_symbol!("lua_type"),
+
+			#[cfg(test)]
+			lua_typename: find_symbol!("lua_typename"),
+
+			path: path.to_path_buf(),
+		})
 	}
 
-	#[cfg(target_os = "windows")]
-	unsafe fn find_library() -> Library {
-		match Library::new("lua_shared.dll") {
-			Ok(library) => library,
-			Err(error) => {
-				eprintln!("Failed to load lua_shared.dll, tier0.dll or vstdlib.dll!");
+	unsafe fn find_symbol<'lib, T>(library: &'lib Library, path: &Path, name: &str) -> Result<Symbol<'lib, T>, LoadError> {
+		let mut symbol = Vec::with_capacity(name.len() + 1);
+		symbol.extend_from_slice(name.as_bytes());
+		symbol.push(0);
 
-				#[cfg(target_pointer_width = "32")]
-				eprintln!("Make sure you are using the 32-bit module binaries from the 32-bit branch of Garry's Mod.");
+		library.get(&symbol).map_err(|error| LoadError::SymbolNotFound {
+			library: path.to_path_buf(),
+			symbol: name.to_string(),
+			error: error.to_string(),
+		})
+	}
 
-				#[cfg(target_pointer_width = "64")]
-				eprintln!("Make sure you are using the 64-bit module binaries from the 64-bit branch of Garry's Mod.");
+	/// The LuaJIT linked into this executable by the `luajit` feature.
+	#[cfg(feature = "luajit")]
+	pub fn linked() -> &'static LuaApi {
+		lazy_static::lazy_static! {
+			static ref LINKED: LuaApi = LuaApi {
+				path: std::env::current_exe().unwrap_or_default(),
+
+				lual_newstate: linked::luaL_newstate,
+				lual_openlibs: linked::luaL_openlibs,
+				lual_loadfile: linked::luaL_loadfile,
+				lual_loadstring: linked::luaL_loadstring,
+				lual_loadbuffer: linked::luaL_loadbuffer,
+				lua_getfield: linked::lua_getfield,
+				lua_pushvalue: linked::lua_pushvalue,
+				lua_pushboolean: linked::lua_pushboolean,
+				lua_tolstring: linked::lua_tolstring,
+				lua_pcall: linked::lua_pcall,
+				lua_remove: linked::lua_remove,
+				lua_close: linked::lua_close,
 
-				eprintln!("The binaries must be placed in the same directory as the executable, or be in the system's PATH.");
+				#[cfg(test)]
+				lua_gettop: linked::lua_gettop,
 
-				eprintln!("Executable path: {:?}", std::env::current_exe().ok());
+				#[cfg(test)]
+				lua_type: linked::lua_type,
 
-				panic!("{:#?}", error);
-			}
+				#[cfg(test)]
+				lua_typename: linked::lua_typename,
+			};
 		}
+		&LINKED
 	}
 
-	#[cfg(not(target_os = "windows"))]
-	unsafe fn find_library() -> Library {
-		let result_srv = Library::new("lua_shared_srv.so");
-		if let Ok(result_srv) = result_srv {
-			return result_srv;
-		}
-
-		match Library::new("lua_shared.so") {
-			Ok(library) => library,
-			Err(error) => {
-				eprintln!("Failed to load lua_shared_srv.so/lua_shared.so, libtier0_srv.so/libtier0.so or libvstdlib_srv.so/libvstdlib.so!");
-
-				#[cfg(target_pointer_width = "32")]
-				eprintln!("Make sure you are using the 32-bit module binaries from the 32-bit branch of Garry's Mod.");
-
-				#[cfg(target_pointer_width = "64")]
-				eprintln!("Make sure you are using the 64-bit module binaries from the 64-bit branch of Garry's Mod.");
-
-				eprintln!("The binaries must be placed in the same directory as the executable, or be in the system's PATH.");
-				eprintln!("You may need to add the directory of the current executable to the LD_LIBRARY_PATH environment variable.");
-
-				eprintln!("Executable path: {:?}", std::env::current_exe().ok());
+	/// The path of the file this library was loaded from, even if the dynamic linker found it by name.
+	/// For the LuaJIT linked in by the `luajit` feature, this is the path of the executable.
+	///
+	/// If the file couldn't be found out, this is the path the library was loaded by.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+}
+impl std::fmt::Debug for LuaApi {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("LuaApi").field("path", &self.path).finish()
+	}
+}
 
-				panic!("{:#?}\n{:#?}", error, result_srv.unwrap_err());
-			}
-		}
+/// The Lua C API of the LuaJIT built and linked by the `luajit` feature's build script.
+#[cfg(feature = "luajit")]
+#[allow(non_snake_case)]
+mod linked {
+	use super::{LuaInt, LuaSize, LuaStatePtr, LuaString};
+
+	extern "C" {
+		pub(super) fn luaL_newstate() -> LuaStatePtr;
+		pub(super) fn luaL_openlibs(state: LuaStatePtr);
+		pub(super) fn luaL_loadfile(state: LuaStatePtr, path: LuaString) -> LuaInt;
+		pub(super) fn luaL_loadstring(state: LuaStatePtr, path: LuaString) -> LuaInt;
+		pub(super) fn luaL_loadbuffer(state: LuaStatePtr, buff: LuaString, size: LuaSize, name: LuaString) -> LuaInt;
+		pub(super) fn lua_getfield(state: LuaStatePtr, index: LuaInt, k: LuaString);
+		pub(super) fn lua_pushvalue(state: LuaStatePtr, index: LuaInt);
+		pub(super) fn lua_pushboolean(state: LuaStatePtr, bool: LuaInt);
+		pub(super) fn lua_tolstring(state: LuaStatePtr, index: LuaInt, out_size: *mut LuaSize) -> LuaString;
+		pub(super) fn lua_pcall(state: LuaStatePtr, nargs: LuaInt, nresults: LuaInt, errfunc: LuaInt) -> LuaInt;
+		pub(super) fn lua_remove(state: LuaStatePtr, index: LuaInt);
+		pub(super) fn lua_close(state: LuaStatePtr);
+
+		#[cfg(test)]
+		pub(super) fn lua_gettop(state: LuaStatePtr) -> LuaInt;
+
+		#[cfg(test)]
+		pub(super) fn lua_type(state: LuaStatePtr, index: LuaInt) -> LuaInt;
+
+		#[cfg(test)]
+		pub(super) fn lua_typename(state: LuaStatePtr, lua_type_id: LuaInt) -> LuaString;
 	}
 }
 
-#[repr(transparent)]
 #[derive(Clone, Copy, Debug)]
-pub(crate) struct LuaState(*const std::ffi::c_void);
+pub(crate) struct LuaState {
+	ptr: LuaStatePtr,
+	api: &'static LuaApi,
+}
 unsafe impl Send for LuaState {}
 impl LuaState {
-	pub(crate) unsafe fn new() -> Result<Self, LuaError> {
-		let lua = (LUA_SHARED.lual_newstate)();
-		(LUA_SHARED.lual_openlibs)(lua);
-		if lua.is_null() {
-			Err(LuaError::MemoryAllocationError)
-		} else {
-			Ok(lua)
+	pub(crate) unsafe fn new(api: &'static LuaApi) -> Result<Self, LuaError> {
+		let ptr = (api.lual_newstate)();
+		if ptr.is_null() {
+			return Err(LuaError::MemoryAllocationError);
 		}
+		(api.lual_openlibs)(ptr);
+
+		Ok(Self { ptr, api })
 	}
 
 	#[inline]
 	#[cfg(test)]
 	pub(crate) unsafe fn get_top(&self) -> LuaInt {
-		(LUA_SHARED.lua_gettop)(*self)
+		(self.api.lua_gettop)(self.ptr)
 	}
 
 	#[cfg(test)]
 	pub(crate) unsafe fn get_type(&self, index: LuaInt) -> std::borrow::Cow<'_, str> {
-		let lua_type = (LUA_SHARED.lua_type)(*self, index);
-		let lua_type_str_ptr = (LUA_SHARED.lua_typename)(*self, lua_type);
+		let lua_type = (self.api.lua_type)(self.ptr, index);
+		let lua_type_str_ptr = (self.api.lua_typename)(self.ptr, lua_type);
 		let lua_type_str = std::ffi::CStr::from_ptr(lua_type_str_ptr);
 		lua_type_str.to_string_lossy()
 	}
 
 	pub(crate) unsafe fn remove(&self, index: LuaInt) {
-		(LUA_SHARED.lua_remove)(*self, index)
+		(self.api.lua_remove)(self.ptr, index)
 	}
 
 	#[inline]
 	pub(crate) unsafe fn push_value(&self, index: LuaInt) {
-		(LUA_SHARED.lua_pushvalue)(*self, index)
+		(self.api.lua_pushvalue)(self.ptr, index)
 	}
 
 	#[inline]
 	pub(crate) unsafe fn get_field(&self, index: LuaInt, k: LuaString) {
-		(LUA_SHARED.lua_getfield)(*self, index, k)
+		(self.api.lua_getfield)(self.ptr, index, k)
 	}
 
 	#[inline]
 	pub(crate) unsafe fn push_boolean(&self, boolean: bool) {
-		(LUA_SHARED.lua_pushboolean)(*self, if boolean { 1 } else { 0 })
+		(self.api.lua_pushboolean)(self.ptr, if boolean { 1 } else { 0 })
 	}
 
 	#[inline]
 	pub(crate) unsafe fn pcall(&self, nargs: LuaInt, nresults: LuaInt, errfunc: LuaInt) -> LuaInt {
-		(LUA_SHARED.lua_pcall)(*self, nargs, nresults, errfunc)
+		(self.api.lua_pcall)(self.ptr, nargs, nresults, errfunc)
 	}
 
 	pub(crate) unsafe fn get_binary_string(&self, index: LuaInt) -> Option<Vec<u8>> {
 		let mut len: usize = 0;
-		let ptr = (LUA_SHARED.lua_tolstring)(*self, index, &mut len);
+		let ptr = (self.api.lua_tolstring)(self.ptr, index, &mut len);
 
 		if ptr.is_null() {
 			return None;
@@ -227,7 +327,7 @@ impl LuaState {
 
 	pub(crate) unsafe fn get_string(&self, index: LuaInt) -> Option<std::borrow::Cow<'_, str>> {
 		let mut len: usize = 0;
-		let ptr = (LUA_SHARED.lua_tolstring)(*self, index, &mut len);
+		let ptr = (self.api.lua_tolstring)(self.ptr, index, &mut len);
 
 		if ptr.is_null() {
 			return None;
@@ -240,7 +340,16 @@ impl LuaState {
 	}
 
 	pub(crate) unsafe fn load_string(&self, src: LuaString) -> Result<(), LuaError> {
-		let lua_error_code = (LUA_SHARED.lual_loadstring)(*self, src);
+		let lua_error_code = (self.api.lual_loadstring)(self.ptr, src);
+		if lua_error_code == 0 {
+			Ok(())
+		} else {
+			Err(LuaError::from_lua_state(*self, lua_error_code))
+		}
+	}
+
+	pub(crate) unsafe fn load_buffer(&self, buf: &[u8], chunk_name: LuaString) -> Result<(), LuaError> {
+		let lua_error_code = (self.api.lual_loadbuffer)(self.ptr, buf.as_ptr() as LuaString, buf.len(), chunk_name);
 		if lua_error_code == 0 {
 			Ok(())
 		} else {
@@ -249,7 +358,7 @@ impl LuaState {
 	}
 
 	pub(crate) unsafe fn load_file(&self, path: LuaString) -> Result<(), LuaError> {
-		let lua_error_code = (LUA_SHARED.lual_loadfile)(*self, path);
+		let lua_error_code = (self.api.lual_loadfile)(self.ptr, path);
 		if lua_error_code == 0 {
 			Ok(())
 		} else {
@@ -259,13 +368,13 @@ impl LuaState {
 
 	#[inline]
 	pub(crate) unsafe fn close(&self) {
-		(LUA_SHARED.lua_close)(*self)
+		(self.api.lua_close)(self.ptr)
 	}
 }
 impl std::ops::Deref for LuaState {
 	type Target = *const std::ffi::c_void;
 
 	fn deref(&self) -> &Self::Target {
-		&self.0
+		&self.ptr
 	}
 }
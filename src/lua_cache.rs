@@ -0,0 +1,117 @@
+//! Reading and writing the files Garry's Mod caches clientside Lua in (`garrysmod/cache/lua/*.lua`).
+//!
+//! When a client joins a server, the Lua files the server sends are stored in the cache so they don't need to be downloaded again.
+//! Each entry is laid out as:
+//!
+//! * the SHA-1 hash of the decompressed contents (20 bytes)
+//! * the contents, compressed like `util.Compress` does: LZMA properties (5 bytes), the decompressed size (8 bytes, little-endian)
+//!   and the LZMA stream
+//!
+//! The entry's file name is the hash in lowercase hex. The contents are usually Lua source code, which may be followed by a NUL byte,
+//! but can also be bytecode.
+//!
+//! ```no_run
+//! use gluac_rs::lua_cache::LuaCacheEntry;
+//!
+//! // Pre-seed the cache with compiled bytecode
+//! let compiler = gluac_rs::compiler().unwrap();
+//! let entry = LuaCacheEntry::new(compiler.compile_path("lua/autorun/client/init.lua", true).unwrap());
+//! std::fs::write(std::path::Path::new("garrysmod/cache/lua").join(entry.file_name()), entry.write()).unwrap();
+//!
+//! // Inspect an entry
+//! let entry = LuaCacheEntry::read(&std::fs::read("garrysmod/cache/lua/052e367c8ad4e2b7f258c73b99b23a021d1705f5.lua").unwrap()).unwrap();
+//! println!("{}", String::from_utf8_lossy(&entry.contents));
+//! ```
+
+use sha1::{Digest, Sha1};
+
+/// The length of the SHA-1 hash at the start of every entry.
+pub const HASH_LEN: usize = 20;
+
+/// An entry in the Lua cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LuaCacheEntry {
+	/// The decompressed contents, kept as they are so that the hash doesn't change. Source code may end with a NUL byte.
+	pub contents: Vec<u8>,
+}
+impl LuaCacheEntry {
+	/// Creates an entry containing Lua source code or bytecode.
+	pub fn new<D: Into<Vec<u8>>>(contents: D) -> Self {
+		Self { contents: contents.into() }
+	}
+
+	/// Decompresses an entry, checking its hash.
+	pub fn read(data: &[u8]) -> Result<LuaCacheEntry, LuaCacheError> {
+		if data.len() < HASH_LEN {
+			return Err(LuaCacheError::TooShort);
+		}
+		let (hash, mut compressed) = data.split_at(HASH_LEN);
+
+		let mut contents = Vec::new();
+		lzma_rs::lzma_decompress(&mut compressed, &mut contents).map_err(|error| LuaCacheError::Decompress(error.to_string()))?;
+
+		let actual: [u8; HASH_LEN] = Sha1::digest(&contents).into();
+		if actual[..] != *hash {
+			let mut expected = [0; HASH_LEN];
+			expected.copy_from_slice(hash);
+			return Err(LuaCacheError::HashMismatch { expected, actual });
+		}
+
+		Ok(LuaCacheEntry { contents })
+	}
+
+	/// Compresses the entry.
+	///
+	/// The LZMA stream is valid but isn't compressed much, as only literals are encoded.
+	pub fn write(&self) -> Vec<u8> {
+		let mut data = Vec::with_capacity(HASH_LEN + 13 + self.contents.len() + self.contents.len() / 8 + 16);
+		data.extend_from_slice(&self.hash());
+
+		let options = lzma_rs::compress::Options {
+			unpacked_size: lzma_rs::compress::UnpackedSize::WriteToHeader(Some(self.contents.len() as u64)),
+		};
+		lzma_rs::lzma_compress_with_options(&mut &self.contents[..], &mut data, &options).expect("Compressing into memory shouldn't fail");
+		data
+	}
+
+	/// The SHA-1 hash of the contents.
+	pub fn hash(&self) -> [u8; HASH_LEN] {
+		Sha1::digest(&self.contents).into()
+	}
+
+	/// The name of the entry's file in `garrysmod/cache/lua`.
+	pub fn file_name(&self) -> String {
+		let hash = self.hash();
+		let mut file_name = hash.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+		file_name.push_str(".lua");
+		file_name
+	}
+
+	/// Whether the contents are LuaJIT bytecode rather than Lua source code.
+	pub fn is_bytecode(&self) -> bool {
+		self.contents.starts_with(crate::bytecode::SIGNATURE)
+	}
+}
+
+/// An error encountered while reading a Lua cache entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LuaCacheError {
+	/// The entry is too short to contain a hash.
+	TooShort,
+
+	/// The contents couldn't be decompressed.
+	Decompress(String),
+
+	/// The decompressed contents don't match the entry's hash.
+	HashMismatch { expected: [u8; HASH_LEN], actual: [u8; HASH_LEN] },
+}
+impl std::fmt::Display for LuaCacheError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			LuaCacheError::TooShort => write!(f, "Lua cache entry is too short"),
+			LuaCacheError::Decompress(error) => write!(f, "failed to decompress Lua cache entry: {}", error),
+			LuaCacheError::HashMismatch { .. } => write!(f, "Lua cache entry doesn't match its SHA-1 hash"),
+		}
+	}
+}
+impl std::error::Error for LuaCacheError {}
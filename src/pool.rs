@@ -0,0 +1,142 @@
+use std::{
+	path::Path,
+	sync::{Condvar, PoisonError},
+};
+
+use crate::{lua::LuaString, Bytecode, BytecodeCompiler, LuaError};
+
+/// A pool of bytecode compilers, each with their own Lua state, for compiling on multiple threads at once.
+///
+/// A single [`BytecodeCompiler`] locks its Lua state for every compilation, so sharing one between threads serializes them.
+/// `CompilerPool` hands each compilation a compiler that isn't in use, waiting for one to become available if they all are.
+///
+/// Create one with [`gluac::compiler_pool()`](crate::compiler_pool) or [`CompilerBuilder::build_pool`](crate::CompilerBuilder::build_pool).
+#[derive(Debug)]
+pub struct CompilerPool {
+	compilers: Vec<BytecodeCompiler>,
+	available: std::sync::Mutex<Vec<usize>>,
+	returned: Condvar,
+}
+impl CompilerPool {
+	pub(crate) fn new(compilers: Vec<BytecodeCompiler>) -> Self {
+		Self {
+			available: std::sync::Mutex::new((0..compilers.len()).collect()),
+			returned: Condvar::new(),
+			compilers,
+		}
+	}
+
+	/// Returns the number of compilers (Lua states) in this pool.
+	pub fn size(&self) -> usize {
+		self.compilers.len()
+	}
+
+	/// Returns the path of the `lua_shared` library the compilers in this pool are using.
+	pub fn library_path(&self) -> &Path {
+		self.compilers[0].library_path()
+	}
+
+	/// Takes a compiler out of the pool, waiting for one to become available if they're all in use.
+	///
+	/// The compiler is returned to the pool when the returned guard is dropped.
+	pub fn get(&self) -> PooledCompiler<'_> {
+		// The list of available compilers is always left in a valid state, so it's fine to ignore poisoning
+		let mut available = self.available.lock().unwrap_or_else(PoisonError::into_inner);
+		loop {
+			if let Some(index) = available.pop() {
+				return PooledCompiler { pool: self, index };
+			}
+			available = self.returned.wait(available).unwrap_or_else(PoisonError::into_inner);
+		}
+	}
+
+	/// Takes a compiler out of the pool if one is available, without waiting.
+	pub fn try_get(&self) -> Option<PooledCompiler<'_>> {
+		let index = self.available.lock().unwrap_or_else(PoisonError::into_inner).pop()?;
+		Some(PooledCompiler { pool: self, index })
+	}
+
+	/// See [`BytecodeCompiler::compile_str`]
+	pub fn compile_str(&self, src: &str, strip_debug: bool) -> Result<Bytecode, LuaError> {
+		self.get().compile_str(src, strip_debug)
+	}
+
+	/// See [`BytecodeCompiler::compile_bytes`]
+	pub fn compile_bytes<S: AsRef<[u8]>>(&self, src: S, strip_debug: bool) -> Result<Bytecode, LuaError> {
+		self.get().compile_bytes(src, strip_debug)
+	}
+
+	/// See [`BytecodeCompiler::compile_named`]
+	pub fn compile_named<S: AsRef<[u8]>>(&self, src: S, chunk_name: &str, strip_debug: bool) -> Result<Bytecode, LuaError> {
+		self.get().compile_named(src, chunk_name, strip_debug)
+	}
+
+	/// See [`BytecodeCompiler::compile_path`]
+	pub fn compile_path<P: AsRef<Path>>(&self, path: P, strip_debug: bool) -> Result<Bytecode, LuaError> {
+		self.get().compile_path(path, strip_debug)
+	}
+
+	/// See [`BytecodeCompiler::compile_string`]
+	#[allow(clippy::not_unsafe_ptr_arg_deref)]
+	pub fn compile_string(&self, src: LuaString, strip_debug: bool) -> Result<Bytecode, LuaError> {
+		self.get().compile_string(src, strip_debug)
+	}
+
+	/// See [`BytecodeCompiler::compile_file`]
+	#[allow(clippy::not_unsafe_ptr_arg_deref)]
+	pub fn compile_file(&self, path: LuaString, strip_debug: bool) -> Result<Bytecode, LuaError> {
+		self.get().compile_file(path, strip_debug)
+	}
+
+	/// See [`BytecodeCompiler::check_str`]
+	pub fn check_str(&self, src: &str) -> Result<(), LuaError> {
+		self.get().check_str(src)
+	}
+
+	/// See [`BytecodeCompiler::check_bytes`]
+	pub fn check_bytes<S: AsRef<[u8]>>(&self, src: S) -> Result<(), LuaError> {
+		self.get().check_bytes(src)
+	}
+
+	/// See [`BytecodeCompiler::check_named`]
+	pub fn check_named<S: AsRef<[u8]>>(&self, src: S, chunk_name: &str) -> Result<(), LuaError> {
+		self.get().check_named(src, chunk_name)
+	}
+
+	/// See [`BytecodeCompiler::check_path`]
+	pub fn check_path<P: AsRef<Path>>(&self, path: P) -> Result<(), LuaError> {
+		self.get().check_path(path)
+	}
+
+	/// See [`BytecodeCompiler::check_string`]
+	#[allow(clippy::not_unsafe_ptr_arg_deref)]
+	pub fn check_string(&self, src: LuaString) -> Result<(), LuaError> {
+		self.get().check_string(src)
+	}
+
+	/// See [`BytecodeCompiler::check_file`]
+	#[allow(clippy::not_unsafe_ptr_arg_deref)]
+	pub fn check_file(&self, path: LuaString) -> Result<(), LuaError> {
+		self.get().check_file(path)
+	}
+}
+
+/// A compiler taken out of a [`CompilerPool`]. It's returned to the pool when dropped.
+#[derive(Debug)]
+pub struct PooledCompiler<'a> {
+	pool: &'a CompilerPool,
+	index: usize,
+}
+impl std::ops::Deref for PooledCompiler<'_> {
+	type Target = BytecodeCompiler;
+
+	fn deref(&self) -> &Self::Target {
+		&self.pool.compilers[self.index]
+	}
+}
+impl Drop for PooledCompiler<'_> {
+	fn drop(&mut self) {
+		self.pool.available.lock().unwrap_or_else(PoisonError::into_inner).push(self.index);
+		self.pool.returned.notify_one();
+	}
+}
@@ -0,0 +1,122 @@
+//! Working out whether a Lua file runs on the server, the client or both, from Garry's Mod's naming conventions.
+//!
+//! Garry's Mod runs the files in `lua/autorun` on both realms, those in `lua/autorun/server` on the server and those in `lua/autorun/client`
+//! on the client. Other files are included by name, and by convention are prefixed with `sv_`, `cl_` or `sh_`. In gamemodes
+//! (`gamemodes/*/gamemode`) and scripted entities, weapons and effects (`entities/*`, `weapons/*` and `effects/*`), `init.lua` runs on the
+//! server, `cl_init.lua` on the client and `shared.lua` on both. Elsewhere, those names don't say anything.
+//!
+//! ```
+//! use gluac_rs::realm::{Ambiguity, Realm};
+//!
+//! assert_eq!(Realm::classify("autorun/server/sv_init.lua".as_ref()), Ok(Realm::Server));
+//! assert_eq!(Realm::classify("entities/my_entity/cl_init.lua".as_ref()), Ok(Realm::Client));
+//! assert_eq!(Realm::classify("mymodule/util.lua".as_ref()), Err(Ambiguity::Unknown));
+//! ```
+
+use std::path::{Component, Path};
+
+/// Where a Lua file runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Realm {
+	Server,
+	Client,
+
+	/// Both the server and the client.
+	Shared,
+}
+impl Realm {
+	/// Classifies a file by its path, which may be relative to the `lua` directory or anything above it.
+	///
+	/// The directory the file is in and its name's prefix must agree, otherwise the file is [`Ambiguity::Conflict`]ing.
+	pub fn classify(path: &Path) -> Result<Realm, Ambiguity> {
+		let components = path
+			.components()
+			.filter_map(|component| match component {
+				Component::Normal(component) => Some(component.to_string_lossy().to_lowercase()),
+				_ => None,
+			})
+			.collect::<Vec<_>>();
+		let file_name = match components.last() {
+			Some(file_name) => file_name.as_str(),
+			None => return Err(Ambiguity::Unknown),
+		};
+
+		let folder = components
+			.iter()
+			.position(|component| component == "autorun")
+			.and_then(|autorun| match &components[autorun + 1..] {
+				[_] => Some(Realm::Shared),
+				[realm, _, ..] if realm == "server" => Some(Realm::Server),
+				[realm, _, ..] if realm == "client" => Some(Realm::Client),
+				_ => None,
+			});
+
+		let prefix = if file_name.starts_with("sv_") {
+			Some(Realm::Server)
+		} else if file_name.starts_with("cl_") {
+			Some(Realm::Client)
+		} else if file_name.starts_with("sh_") {
+			Some(Realm::Shared)
+		} else {
+			None
+		};
+
+		match (folder, prefix) {
+			(Some(folder), Some(name)) if folder != name => Err(Ambiguity::Conflict { folder, name }),
+			(Some(realm), _) | (None, Some(realm)) => Ok(realm),
+			(None, None) if is_scripted_folder(&components) => match file_name {
+				"init.lua" => Ok(Realm::Server),
+				"shared.lua" => Ok(Realm::Shared),
+				_ => Err(Ambiguity::Unknown),
+			},
+			(None, None) => Err(Ambiguity::Unknown),
+		}
+	}
+
+	/// Whether files of this realm run on the server.
+	pub fn is_server(self) -> bool {
+		matches!(self, Realm::Server | Realm::Shared)
+	}
+
+	/// Whether files of this realm run on the client, and so must be sent to it.
+	pub fn is_client(self) -> bool {
+		matches!(self, Realm::Client | Realm::Shared)
+	}
+}
+impl std::fmt::Display for Realm {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Realm::Server => write!(f, "server"),
+			Realm::Client => write!(f, "client"),
+			Realm::Shared => write!(f, "shared"),
+		}
+	}
+}
+
+/// Whether a file is directly inside a gamemode's `gamemode` directory, or a scripted entity, weapon or effect's directory.
+fn is_scripted_folder(components: &[String]) -> bool {
+	match components {
+		[.., gamemodes, _, gamemode, _] if gamemodes == "gamemodes" && gamemode == "gamemode" => true,
+		[.., scripted, _, _] => matches!(scripted.as_str(), "entities" | "weapons" | "effects"),
+		_ => false,
+	}
+}
+
+/// Why a file's realm couldn't be worked out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ambiguity {
+	/// Neither the file's name nor its directory follow any of the conventions.
+	Unknown,
+
+	/// The file's directory and its name's prefix say it runs in different realms.
+	Conflict { folder: Realm, name: Realm },
+}
+impl std::fmt::Display for Ambiguity {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Ambiguity::Unknown => write!(f, "neither its name nor its directory say which realm it runs in"),
+			Ambiguity::Conflict { folder, name } => write!(f, "its directory says it's {}, but its name says it's {}", folder, name),
+		}
+	}
+}
+impl std::error::Error for Ambiguity {}
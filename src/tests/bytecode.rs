@@ -0,0 +1,485 @@
+use crate::bytecode::{
+	self, Branch, BytecodeDump, DebugInfo, GcConstant, Header, HeaderFlags, Incompatibility, Instruction, NumConstant, Opcode, ParseError,
+	ProtoFlags, Prototype, TableConstant, TableValue, Upvalue, VarInfo, VarName, WriteError,
+};
+
+use super::tests::{HELLO_WORLD_X86, HELLO_WORLD_X86_64};
+
+fn kitchen_sink_path() -> String {
+	std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+		.join("src/tests/kitchen_sink.lua")
+		.to_string_lossy()
+		.to_string()
+}
+
+pub(super) fn compile_kitchen_sink(compiler: &crate::compiler::BytecodeCompiler, strip_debug: bool) -> crate::Bytecode {
+	let bytecode = compiler.compile_file(lua_string!(kitchen_sink_path()), strip_debug).unwrap();
+	assert_round_trip(&bytecode);
+	bytecode
+}
+
+/// Asserts that parsing and writing bytecode produces the exact same bytes.
+pub(super) fn assert_round_trip(bytecode: &[u8]) {
+	let dump = bytecode::parse(bytecode).unwrap();
+	assert_eq!(dump.write().unwrap(), bytecode);
+}
+
+fn opcodes(proto: &bytecode::Prototype, version: u8) -> Vec<Opcode> {
+	proto
+		.instructions
+		.iter()
+		.map(|instruction| instruction.opcode(version).unwrap())
+		.collect()
+}
+
+#[test]
+fn parse_hello_world_x86_64() {
+	let dump = bytecode::parse(HELLO_WORLD_X86_64).unwrap();
+	assert_eq!(dump.header.version, bytecode::VERSION_2_1);
+	assert_eq!(dump.header.flags, HeaderFlags(HeaderFlags::STRIP.0 | HeaderFlags::FR2.0));
+	assert_eq!(dump.header.chunk_name, None);
+
+	let main = &dump.main;
+	assert_eq!(main.flags, ProtoFlags::VARARG);
+	assert_eq!(main.num_params, 0);
+	assert_eq!(main.frame_size, 3);
+	assert_eq!(main.debug, None);
+	assert_eq!(
+		opcodes(main, dump.header.version),
+		[Opcode::GGet, Opcode::KStr, Opcode::Call, Opcode::Ret0]
+	);
+	assert_eq!(main.instructions[1].a(), 2);
+	assert_eq!(main.gc_constant(main.instructions[0].d()), Some(&GcConstant::String(b"print".to_vec())));
+	assert_eq!(
+		main.gc_constant(main.instructions[1].d()),
+		Some(&GcConstant::String(b"Hello, world!".to_vec()))
+	);
+}
+
+#[test]
+fn parse_hello_world_x86() {
+	assert_round_trip(HELLO_WORLD_X86);
+
+	let dump = bytecode::parse(HELLO_WORLD_X86).unwrap();
+	assert_eq!(dump.header.version, bytecode::VERSION_2_0);
+	assert_eq!(dump.header.flags, HeaderFlags::STRIP);
+
+	let main = &dump.main;
+	assert_eq!(main.frame_size, 2);
+	assert_eq!(
+		opcodes(main, dump.header.version),
+		[Opcode::GGet, Opcode::KStr, Opcode::Call, Opcode::Ret0]
+	);
+	assert_eq!(main.instructions[1].a(), 1);
+	assert_eq!(main.gc_constants, bytecode::parse(HELLO_WORLD_X86_64).unwrap().main.gc_constants);
+}
+
+#[test]
+fn opcode_versions() {
+	for op in 0..=96 {
+		let opcode = Opcode::from_u8(op, bytecode::VERSION_2_1).unwrap();
+		assert_eq!(opcode.to_u8(bytecode::VERSION_2_1), Some(op));
+	}
+	for op in 0..=92 {
+		let opcode = Opcode::from_u8(op, bytecode::VERSION_2_0).unwrap();
+		assert_eq!(opcode.to_u8(bytecode::VERSION_2_0), Some(op));
+	}
+	assert_eq!(Opcode::from_u8(97, bytecode::VERSION_2_1), None);
+	assert_eq!(Opcode::from_u8(93, bytecode::VERSION_2_0), None);
+	assert_eq!(Opcode::TGetR.to_u8(bytecode::VERSION_2_0), None);
+}
+
+#[test]
+fn parse_errors() {
+	assert_eq!(bytecode::parse(b"print('Hello, world!')"), Err(ParseError::InvalidSignature));
+	assert_eq!(bytecode::parse(b"\x1BLJ\x03\x02"), Err(ParseError::UnsupportedVersion(3)));
+	assert_eq!(bytecode::parse(b"\x1BLJ\x01\x0A"), Err(ParseError::UnknownFlags(0x0A)));
+	assert_eq!(
+		bytecode::parse(&HELLO_WORLD_X86_64[..HELLO_WORLD_X86_64.len() - 1]),
+		Err(ParseError::UnexpectedEof)
+	);
+
+	let mut trailing = HELLO_WORLD_X86_64.to_vec();
+	trailing.push(0);
+	assert_eq!(
+		bytecode::parse(&trailing),
+		Err(ParseError::TrailingData {
+			offset: HELLO_WORLD_X86_64.len()
+		})
+	);
+}
+
+#[test]
+fn parse_malformed_bytecode() {
+	let compiler = super::compiler().unwrap();
+	for bytecode in [compile_kitchen_sink(&compiler, false), compile_kitchen_sink(&compiler, true)].iter() {
+		for len in 0..bytecode.len() {
+			assert!(bytecode::parse(&bytecode[..len]).is_err(), "truncated to {} bytes", len);
+		}
+
+		// Corrupted bytecode may still parse, but must never panic
+		let mut corrupted = bytecode.clone();
+		for offset in 0..bytecode.len() {
+			for byte in [0x00, 0x7F, 0x80, 0xFF, bytecode[offset] ^ 1].iter() {
+				corrupted[offset] = *byte;
+				let _ = bytecode::disassemble(&corrupted);
+			}
+			corrupted[offset] = bytecode[offset];
+		}
+	}
+
+	// Counts that claim far more items than there is data for
+	let huge_gc_constants = b"\x1BLJ\x02\x02\x0A\x00\x00\x00\x00\xFF\xFF\xFF\xFF\x0F\x00\x00";
+	assert_eq!(bytecode::parse(huge_gc_constants), Err(ParseError::UnexpectedEof));
+
+	let huge_debug_info = b"\x1BLJ\x02\x00\x00\x0A\x00\x00\x00\x00\x00\x00\x00\xFF\xFF\xFF\xFF\x0F\x00\x00";
+	assert_eq!(bytecode::parse(huge_debug_info), Err(ParseError::InvalidDebugInfo { offset: 7 }));
+}
+
+#[test]
+fn parse_kitchen_sink() {
+	let compiler = super::compiler().unwrap();
+
+	let dump = bytecode::parse(&compile_kitchen_sink(&compiler, false)).unwrap();
+	assert!(!dump.header.flags.contains(HeaderFlags::STRIP));
+	assert_eq!(dump.header.chunk_name, Some(format!("@{}", kitchen_sink_path()).into_bytes()));
+
+	let main = &dump.main;
+	assert!(main.flags.contains(ProtoFlags::CHILD));
+	assert_eq!(main.children().count(), 4);
+
+	let table = main
+		.gc_constants
+		.iter()
+		.find_map(|constant| match constant {
+			GcConstant::Table(table) => Some(table),
+			_ => None,
+		})
+		.unwrap();
+	assert!(table.array.contains(&TableValue::String(b"first".to_vec())));
+	assert!(table.array.contains(&TableValue::Num(4.5)));
+	assert!(table
+		.hash
+		.contains(&(TableValue::String(b"name".to_vec()), TableValue::String(b"kitchen sink".to_vec()))));
+	assert_eq!(table.array[10], TableValue::String(b"ten".to_vec()));
+
+	assert!(main.num_constants.contains(&NumConstant::Int(2147483647)));
+	assert!(main.num_constants.contains(&NumConstant::Int(-1234567)));
+	assert!(main.num_constants.contains(&NumConstant::Num(1e300)));
+
+	let debug = main.debug.as_ref().unwrap();
+	assert_eq!(debug.first_line, 0);
+	assert_eq!(debug.lines.len(), main.instructions.len());
+	assert_eq!(debug.lines[0], 1);
+	assert!(debug.variables.iter().any(|var| var.name == VarName::Named(b"config".to_vec())));
+	assert!(debug.variables.iter().any(|var| var.name == VarName::ForGenerator));
+
+	let sum = main.children().find(|child| child.flags.contains(ProtoFlags::VARARG)).unwrap();
+	let sum_debug = sum.debug.as_ref().unwrap();
+	assert_eq!(sum_debug.first_line, 15);
+	assert!(sum_debug.variables.iter().any(|var| var.name == VarName::ForIndex));
+
+	let increment = main.children().find(|child| child.children().count() == 1).unwrap();
+	let closure = increment.children().next().unwrap();
+	assert_eq!(
+		closure.debug.as_ref().unwrap().upvalue_names,
+		[b"counter".to_vec(), b"big".to_vec(), b"negative".to_vec()]
+	);
+
+	let stripped = bytecode::parse(&compile_kitchen_sink(&compiler, true)).unwrap();
+	assert!(stripped.header.flags.contains(HeaderFlags::STRIP));
+	assert_eq!(stripped.main.debug, None);
+	assert_eq!(stripped.main.instructions, main.instructions);
+}
+
+#[test]
+fn parse_wide_line_info() {
+	let compiler = super::compiler().unwrap();
+
+	for lines in [300, 70000].iter() {
+		let src = format!("local x = ...{}return x", "\n".repeat(*lines));
+		let bytecode = compiler.compile_string(lua_string!(src), false).unwrap();
+		assert_round_trip(&bytecode);
+
+		let dump = bytecode::parse(&bytecode).unwrap();
+		let debug = dump.main.debug.unwrap();
+		assert_eq!(debug.num_lines, *lines as u32 + 1);
+		assert_eq!(debug.lines.last(), Some(&(*lines as u32 + 1)));
+	}
+}
+
+#[test]
+fn disassemble_hello_world() {
+	let expected = "\
+-- BYTECODE -- ?:0-0
+0001    GGET     0   0      ; \"print\"
+0002    KSTR     2   1      ; \"Hello, world!\"
+0003    CALL     0   1   2
+0004    RET0     0   1
+
+";
+	assert_eq!(bytecode::disassemble(HELLO_WORLD_X86_64).unwrap(), expected);
+	assert_eq!(
+		bytecode::disassemble(HELLO_WORLD_X86).unwrap(),
+		expected.replace("KSTR     2", "KSTR     1")
+	);
+}
+
+#[test]
+fn disassemble_kitchen_sink() {
+	let compiler = super::compiler().unwrap();
+
+	let listing = bytecode::disassemble(&compile_kitchen_sink(&compiler, false)).unwrap();
+	let chunk_name = kitchen_sink_path();
+
+	// Children are listed before their parents
+	assert!(listing.starts_with(&format!("-- BYTECODE -- {}:15-21\n", chunk_name)));
+	assert!(listing.ends_with("0071 [  61] => RET     11   4\n\n"));
+
+	for line in [
+		"0008 [  17]    FORI     1 => 0015",
+		"0009 [  18] => GGET     5   0      ; \"select\"",
+		"0001 [  27]    UGET     0   0      ; counter",
+		"0006 [  25]    USETV    0   1      ; counter",
+		"0002 [  10]    KNUM     1   0      ; 2147483647",
+		"0004 [  12]    KNUM     3   2      ; -0.5",
+		"0005 [  13]    KNUM     4   3      ; 1e+300",
+		"0038 [  52]    ADDVN    9   9   5  ; 1",
+		"0050 [  59]    TGETS   13  10   6  ; \"greet\"",
+	]
+	.iter()
+	{
+		assert!(
+			listing.lines().any(|listing_line| listing_line == *line),
+			"missing {:?} in\n{}",
+			line,
+			listing
+		);
+	}
+	assert!(listing.contains(&format!("FNEW     5   1      ; {}:15\n", chunk_name)));
+
+	// Line numbers come straight from the dump, so they may be nonsense
+	let mut dump = bytecode::parse(&compile_kitchen_sink(&compiler, false)).unwrap();
+	dump.main.debug.as_mut().unwrap().first_line = u32::MAX;
+	assert!(dump
+		.disassemble()
+		.contains(&format!("-- BYTECODE -- {}:4294967295-4294967295\n", chunk_name)));
+
+	let stripped = bytecode::disassemble(&compile_kitchen_sink(&compiler, true)).unwrap();
+	assert!(stripped.starts_with("-- BYTECODE -- ?:0-0\n"));
+	assert!(stripped.lines().any(|line| line == "0001    UGET     0   0"));
+	assert!(stripped.lines().any(|line| line == "0008    FORI     1 => 0015"));
+}
+
+#[test]
+fn write_edited_bytecode() {
+	let compiler = super::compiler().unwrap();
+
+	let mut dump = bytecode::parse(&compiler.compile_string(lua_string!(r#"print("Hello, world!")"#), true).unwrap()).unwrap();
+	dump.main.gc_constants[1] = GcConstant::String(b"Goodbye, world!".to_vec());
+
+	let expected = compiler.compile_string(lua_string!(r#"print("Goodbye, world!")"#), true).unwrap();
+	assert_eq!(dump.write().unwrap(), expected);
+}
+
+#[test]
+fn write_handmade_bytecode() {
+	let child = Prototype {
+		flags: ProtoFlags::default(),
+		num_params: 1,
+		frame_size: 2,
+		instructions: vec![
+			Instruction::new_ad(Opcode::KCData.to_u8(bytecode::VERSION_2_1).unwrap(), 1, 0),
+			Instruction::new_ad(Opcode::Ret1.to_u8(bytecode::VERSION_2_1).unwrap(), 1, 2),
+		],
+		upvalues: vec![Upvalue(0x8000 | 0x4000)],
+		gc_constants: vec![GcConstant::I64(-1), GcConstant::U64(u64::MAX), GcConstant::Complex(1.5, -2.0)],
+		num_constants: vec![],
+		debug: Some(DebugInfo {
+			first_line: 70000,
+			num_lines: 300,
+			lines: vec![70001, 70300],
+			upvalue_names: vec![b"upvalue".to_vec()],
+			variables: vec![
+				VarInfo {
+					name: VarName::Named(b"x".to_vec()),
+					start_pc: 0,
+					end_pc: 2,
+				},
+				VarInfo {
+					name: VarName::ForIndex,
+					start_pc: 1,
+					end_pc: 2,
+				},
+			],
+		}),
+	};
+
+	let main = Prototype {
+		flags: ProtoFlags(ProtoFlags::CHILD.0 | ProtoFlags::VARARG.0 | ProtoFlags::FFI.0),
+		num_params: 0,
+		frame_size: 1,
+		instructions: vec![
+			Instruction::new_ad(Opcode::FNew.to_u8(bytecode::VERSION_2_1).unwrap(), 0, 1),
+			Instruction::new_ad(Opcode::Ret0.to_u8(bytecode::VERSION_2_1).unwrap(), 0, 1),
+		],
+		upvalues: vec![],
+		gc_constants: vec![
+			GcConstant::Table(TableConstant {
+				array: vec![TableValue::Nil, TableValue::Int(-1), TableValue::Num(0.5)],
+				hash: vec![(TableValue::String(b"key".to_vec()), TableValue::True)],
+			}),
+			GcConstant::Child(child),
+		],
+		num_constants: vec![NumConstant::Int(i32::MIN), NumConstant::Num(f64::MAX), NumConstant::Num(-0.0)],
+		debug: None,
+	};
+
+	for big_endian in [false, true].iter() {
+		let mut flags = HeaderFlags(HeaderFlags::FFI.0 | HeaderFlags::FR2.0);
+		flags.set(HeaderFlags::BIG_ENDIAN, *big_endian);
+
+		let dump = BytecodeDump {
+			header: Header {
+				version: bytecode::VERSION_2_1,
+				flags,
+				chunk_name: Some(b"=handmade".to_vec()),
+			},
+			main: main.clone(),
+		};
+
+		let bytecode = dump.write().unwrap();
+		assert_eq!(bytecode::parse(&bytecode).unwrap(), dump);
+		assert_round_trip(&bytecode);
+	}
+
+	let mut invalid = main;
+	invalid.upvalues = vec![Upvalue(0); 256];
+	let dump = BytecodeDump {
+		header: Header {
+			version: bytecode::VERSION_2_1,
+			flags: HeaderFlags::STRIP,
+			chunk_name: None,
+		},
+		main: invalid,
+	};
+	assert_eq!(dump.write(), Err(WriteError::TooManyUpvalues(256)));
+}
+
+#[test]
+fn strip_compiled_bytecode() {
+	let compiler = super::compiler().unwrap();
+
+	let stripped = compile_kitchen_sink(&compiler, true);
+	assert_eq!(bytecode::strip(&compile_kitchen_sink(&compiler, false)).unwrap(), stripped);
+	assert_eq!(bytecode::strip(&stripped).unwrap(), stripped);
+
+	let hello_world = compiler.compile_string(lua_string!(r#"print("Hello, world!")"#), false).unwrap();
+	#[cfg(target_pointer_width = "64")]
+	assert_eq!(bytecode::strip(&hello_world).unwrap(), HELLO_WORLD_X86_64);
+	#[cfg(target_pointer_width = "32")]
+	assert_eq!(bytecode::strip(&hello_world).unwrap(), HELLO_WORLD_X86);
+
+	assert_eq!(bytecode::strip(b"not bytecode"), Err(ParseError::InvalidSignature));
+}
+
+#[test]
+fn header_inspection() {
+	let x86_64 = bytecode::header(HELLO_WORLD_X86_64).unwrap();
+	assert_eq!(x86_64.version, bytecode::VERSION_2_1);
+	assert!(x86_64.is_stripped() && x86_64.is_fr2());
+	assert!(!x86_64.has_ffi() && !x86_64.is_big_endian());
+	assert_eq!(Branch::of(&x86_64), Some(Branch::X86_64));
+	assert_eq!(x86_64.check_branch(Branch::X86_64), Ok(()));
+	assert_eq!(
+		x86_64.check_branch(Branch::X86),
+		Err(Incompatibility::Version {
+			branch: Branch::X86,
+			version: bytecode::VERSION_2_1
+		})
+	);
+
+	let x86 = bytecode::header(HELLO_WORLD_X86).unwrap();
+	assert_eq!(x86.version, bytecode::VERSION_2_0);
+	assert!(x86.is_stripped() && !x86.is_fr2());
+	assert_eq!(Branch::of(&x86), Some(Branch::X86));
+	assert!(x86.check_branch(Branch::X86_64).is_err());
+
+	// LuaJIT 2.1 without two-slot frames, as built for 32-bit targets, can't be loaded by either branch
+	let no_fr2 = bytecode::header(b"\x1BLJ\x02\x02").unwrap();
+	assert_eq!(Branch::of(&no_fr2), None);
+	assert_eq!(
+		no_fr2.check_branch(Branch::X86_64),
+		Err(Incompatibility::FrameLayout {
+			branch: Branch::X86_64,
+			fr2: false
+		})
+	);
+
+	let big_endian = bytecode::header(b"\x1BLJ\x01\x03").unwrap();
+	assert!(big_endian.is_big_endian());
+	assert_eq!(big_endian.check_branch(Branch::X86), Err(Incompatibility::BigEndian));
+
+	// Only the header is read, so bytecode that's cut off after it still has a header
+	assert_eq!(bytecode::header(&HELLO_WORLD_X86_64[..5]), Ok(x86_64));
+	assert_eq!(bytecode::header(b"\x1BLJ\x03\x02"), Err(ParseError::UnsupportedVersion(3)));
+
+	let compiler = super::compiler().unwrap();
+	let bytecode = compiler.compile_str("return", false).unwrap();
+	let header = bytecode::header(&bytecode).unwrap();
+	assert!(!header.is_stripped());
+	assert_eq!(header.chunk_name.as_deref(), Some(&b"return"[..]));
+	assert_eq!(Branch::of(&header), Some(Branch::NATIVE));
+}
+
+#[test]
+fn translate_between_branches() {
+	assert_eq!(bytecode::translate(HELLO_WORLD_X86_64, Branch::X86).unwrap(), HELLO_WORLD_X86);
+	assert_eq!(bytecode::translate(HELLO_WORLD_X86, Branch::X86_64).unwrap(), HELLO_WORLD_X86_64);
+
+	let compiler = super::compiler().unwrap();
+
+	// Translating to the 32-bit branch and back must give back the exact same bytecode
+	let bytecode = compile_kitchen_sink(&compiler, false);
+	let translated = bytecode::translate(&bytecode, Branch::X86).unwrap();
+	assert_eq!(bytecode::header(&translated).unwrap().check_branch(Branch::X86), Ok(()));
+	assert_eq!(bytecode::translate(&translated, Branch::X86_64).unwrap(), bytecode);
+
+	// Nested method calls, whose slots are compared against LuaJIT 2.0's output
+	let bytecode = compiler
+		.compile_string(lua_string!("local t = ...\nt:f(t:g(1), print(\"x\"))"), true)
+		.unwrap();
+	let dump = bytecode::parse(&bytecode).unwrap().translate(Branch::X86).unwrap();
+	let slots = dump
+		.main
+		.instructions
+		.iter()
+		.map(|instruction| (instruction.opcode(bytecode::VERSION_2_0).unwrap(), instruction.a()))
+		.collect::<Vec<_>>();
+	assert_eq!(
+		slots,
+		[
+			(Opcode::VArg, 0),
+			(Opcode::Mov, 2),
+			(Opcode::TGetS, 1),
+			(Opcode::Mov, 4),
+			(Opcode::TGetS, 3),
+			(Opcode::KShort, 5),
+			(Opcode::Call, 3),
+			(Opcode::GGet, 4),
+			(Opcode::KStr, 5),
+			(Opcode::Call, 4),
+			(Opcode::CallM, 1),
+			(Opcode::Ret0, 0),
+		]
+	);
+	assert_eq!(dump.main.frame_size, 6);
+
+	// TGETR only exists in LuaJIT 2.1
+	let mut dump = bytecode::parse(HELLO_WORLD_X86_64).unwrap();
+	dump.main.instructions[0] = Instruction::new_abc(Opcode::TGetR.to_u8(bytecode::VERSION_2_1).unwrap(), 0, 0, 0);
+	assert_eq!(
+		dump.translate(Branch::X86),
+		Err(bytecode::TranslateError::UnsupportedOpcode(Opcode::TGetR))
+	);
+}
@@ -0,0 +1,107 @@
+use crate::CompileCache;
+
+/// A cache in a fresh temporary directory, which is deleted when dropped.
+struct TempCache(CompileCache);
+impl TempCache {
+	fn new(name: &str) -> Self {
+		let dir = std::env::temp_dir().join(format!("gluac-rs-test-{}-{}", name, std::process::id()));
+		let cache = CompileCache::new(dir);
+		cache.clear().unwrap();
+		TempCache(cache)
+	}
+}
+impl std::ops::Deref for TempCache {
+	type Target = CompileCache;
+
+	fn deref(&self) -> &Self::Target {
+		&self.0
+	}
+}
+impl Drop for TempCache {
+	fn drop(&mut self) {
+		self.0.clear().ok();
+	}
+}
+
+#[test]
+fn cache_hits_and_misses() {
+	let compiler = super::compiler().unwrap();
+	let cache = TempCache::new("hits");
+
+	let src = r#"print("Hello, world!")"#;
+	let bytecode = cache.compile_named(&compiler, src, "@hello.lua", false).unwrap();
+	assert_eq!((cache.hits(), cache.misses()), (0, 1));
+	assert_eq!(bytecode, compiler.compile_named(src, "@hello.lua", false).unwrap());
+
+	assert_eq!(cache.compile_named(&compiler, src, "@hello.lua", false).unwrap(), bytecode);
+	assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+	// Each part of the key is taken into account
+	cache.compile_named(&compiler, src, "@hello.lua", true).unwrap();
+	cache.compile_named(&compiler, src, "@goodbye.lua", false).unwrap();
+	cache
+		.compile_named(&compiler, r#"print("Goodbye, world!")"#, "@hello.lua", false)
+		.unwrap();
+	assert_eq!((cache.hits(), cache.misses()), (1, 4));
+
+	// A new cache in the same directory picks up the existing entries
+	let reopened = CompileCache::new(cache.dir());
+	assert_eq!(
+		reopened.compile_named(&compiler, src, "@hello.lua", true).unwrap(),
+		crate::bytecode::strip(&bytecode).unwrap()
+	);
+	assert_eq!((reopened.hits(), reopened.misses()), (1, 0));
+}
+
+#[test]
+fn cache_keys_glua_preprocessing() {
+	let preprocessing = super::builder().glua(true).build().unwrap();
+	let native = super::builder().glua(false).build().unwrap();
+	let cache = TempCache::new("glua");
+
+	// Whether GLua was preprocessed decides whether the source compiled at all, so the entries mustn't be shared
+	cache.compile_named(&preprocessing, "return 1", "@glua.lua", false).unwrap();
+	cache.compile_named(&native, "return 1", "@glua.lua", false).unwrap();
+	assert_eq!((cache.hits(), cache.misses()), (0, 2));
+
+	cache.compile_named(&preprocessing, "return 1", "@glua.lua", false).unwrap();
+	cache.compile_named(&native, "return 1", "@glua.lua", false).unwrap();
+	assert_eq!((cache.hits(), cache.misses()), (2, 2));
+}
+
+#[test]
+fn cache_ignores_failures_and_corruption() {
+	let compiler = super::compiler().unwrap();
+	let cache = TempCache::new("corruption");
+
+	for _ in 0..2 {
+		assert!(matches!(
+			cache.compile_named(&compiler, "Invalid Lua code", "@invalid.lua", true),
+			Err(crate::LuaError::SyntaxError(_))
+		));
+	}
+	assert_eq!((cache.hits(), cache.misses()), (0, 2));
+
+	let bytecode = cache.compile_named(&compiler, "return 1", "@one.lua", true).unwrap();
+	for entry in walkdir::WalkDir::new(cache.dir()).into_iter().filter_map(Result::ok) {
+		if entry.file_type().is_file() {
+			std::fs::write(entry.path(), b"corrupted").unwrap();
+		}
+	}
+	assert_eq!(cache.compile_named(&compiler, "return 1", "@one.lua", true).unwrap(), bytecode);
+	assert_eq!((cache.hits(), cache.misses()), (0, 4));
+}
+
+#[test]
+fn cache_keys_library_file() {
+	let compiler = super::compiler().unwrap();
+	let cache = TempCache::new("library");
+
+	// lua_shared is found by name through the dynamic linker in the tests, but it's the file it was found at that's hashed
+	assert!(compiler.library_path().is_file(), "{}", compiler.library_path().display());
+	assert!(cache.library_hash(compiler.library_path()).is_some());
+
+	// Without knowing which file compiled an entry, it mustn't be cached
+	assert_eq!(cache.library_hash(std::path::Path::new(crate::library::LIBRARY_NAMES[0])), None);
+	assert_eq!(cache.library_hash(&std::env::temp_dir().join("this library does not exist")), None);
+}
@@ -0,0 +1,75 @@
+use crate::{Diagnostic, LuaError};
+
+#[test]
+fn parse_diagnostics() {
+	assert_eq!(
+		Diagnostic::parse("addons/myaddon/lua/autorun/init.lua:12: '=' expected near 'code'"),
+		Some(Diagnostic {
+			chunk: "addons/myaddon/lua/autorun/init.lua".to_string(),
+			line: 12,
+			message: "'=' expected".to_string(),
+			near_token: Some("code".to_string()),
+		})
+	);
+
+	assert_eq!(
+		Diagnostic::parse(r#"[string "x = 'a:1: b'..."]:3: unfinished string near ''oops'"#),
+		Some(Diagnostic {
+			chunk: r#"[string "x = 'a:1: b'..."]"#.to_string(),
+			line: 3,
+			message: "unfinished string".to_string(),
+			near_token: Some("'oops".to_string()),
+		})
+	);
+
+	assert_eq!(
+		Diagnostic::parse(r"C:\garrysmod\lua\init.lua:7: 'end' expected (to close 'function' at line 1) near '<eof>'"),
+		Some(Diagnostic {
+			chunk: r"C:\garrysmod\lua\init.lua".to_string(),
+			line: 7,
+			message: "'end' expected (to close 'function' at line 1)".to_string(),
+			near_token: Some("<eof>".to_string()),
+		})
+	);
+
+	assert_eq!(
+		Diagnostic::parse("stdin:1: chunk has too many syntax levels"),
+		Some(Diagnostic {
+			chunk: "stdin".to_string(),
+			line: 1,
+			message: "chunk has too many syntax levels".to_string(),
+			near_token: None,
+		})
+	);
+
+	assert_eq!(Diagnostic::parse("cannot open this file does not exist"), None);
+	assert_eq!(Diagnostic::parse("[string \"unterminated:1: oops"), None);
+
+	let error = "init.lua:5: unexpected symbol near '<eof>'";
+	assert_eq!(Diagnostic::parse(error).unwrap().to_string(), error);
+}
+
+#[test]
+fn syntax_error_diagnostics() {
+	let compiler = super::compiler().unwrap();
+
+	let error = compiler
+		.compile_named("local x = 1\nInvalid Lua code", "@addons/myaddon/lua/autorun/init.lua", true)
+		.unwrap_err();
+	assert_eq!(
+		error.diagnostic(),
+		Some(Diagnostic {
+			chunk: "addons/myaddon/lua/autorun/init.lua".to_string(),
+			line: 2,
+			message: "'=' expected".to_string(),
+			near_token: Some("Lua".to_string()),
+		})
+	);
+
+	let diagnostic = compiler.compile_str("print(\"Hello, world!\"", true).unwrap_err().diagnostic().unwrap();
+	assert_eq!(diagnostic.chunk, r#"[string "print("Hello, world!""]"#);
+	assert_eq!(diagnostic.line, 1);
+	assert_eq!(diagnostic.near_token.as_deref(), Some("<eof>"));
+
+	assert_eq!(LuaError::FileError(Some("cannot open x".to_string())).diagnostic(), None);
+}
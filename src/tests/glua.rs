@@ -0,0 +1,103 @@
+use crate::glua;
+
+fn to_lua(src: &str) -> String {
+	String::from_utf8(glua::to_lua(src.as_bytes()).into_owned()).unwrap()
+}
+
+/// Compiles GLua and the equivalent standard Lua, which must produce the same stripped bytecode.
+fn assert_compiles_to(compiler: &crate::BytecodeCompiler, glua: &str, lua: &str) {
+	assert_eq!(
+		compiler.compile_str(glua, true).unwrap(),
+		compiler.compile_str(lua, true).unwrap(),
+		"{}",
+		to_lua(glua)
+	);
+}
+
+#[test]
+fn glua_operators_and_comments() {
+	assert_eq!(to_lua("if a != b && !c || d then end"), "if a ~= b and not c or d then end");
+	assert_eq!(to_lua("x = !(a&&b)||!c"), "x = not(a and b)or not c");
+	assert_eq!(to_lua("x = 1 // one"), "x = 1 -- one");
+	assert_eq!(to_lua("x = 1 //[[ not a long comment\ny = 2"), "x = 1 -- [[ not a long comment\ny = 2");
+	assert_eq!(to_lua("x = 1 /* one */ + 2"), "x = 1   + 2");
+	assert_eq!(to_lua("x = a/**/b"), "x = a b");
+	assert_eq!(to_lua("x = 1 /* one\r\ntwo\n*/ y = 2"), "x = 1  \r\n\n y = 2");
+	assert_eq!(to_lua("x = 1 /* unterminated"), "x = 1  ");
+
+	// Standard Lua is left as-is
+	let lua = "local s = \"a != b // c\" .. 'd && e' .. [==[ f || !g ]==] -- h /* i\n--[[ j != k ]] return 1 ~= 2, 3 / 4, #s";
+	assert!(matches!(glua::to_lua(lua.as_bytes()), std::borrow::Cow::Borrowed(_)));
+	assert!(matches!(glua::to_lua(super::tests::HELLO_WORLD_X86_64), std::borrow::Cow::Borrowed(_)));
+}
+
+#[test]
+fn glua_continue() {
+	assert_eq!(
+		to_lua("for i = 1, 10 do if i % 2 == 0 then continue end print(i) end"),
+		"for i = 1, 10 do do if i % 2 == 0 then goto __glua_continue_1 end print(i) end ::__glua_continue_1:: end"
+	);
+	assert_eq!(
+		to_lua("repeat i = i + 1 if i < 5 then continue end until i > 10"),
+		"repeat i = i + 1 if i < 5 then goto __glua_continue_1 end ::__glua_continue_1:: until i > 10"
+	);
+
+	// Nested loops each get their own label, and only loops that use continue are changed
+	assert_eq!(
+		to_lua("while a do for k, v in pairs(t) do continue end while b do end continue end"),
+		"while a do do for k, v in pairs(t) do do goto __glua_continue_1 end ::__glua_continue_1:: end while b do end goto __glua_continue_2 end ::__glua_continue_2:: end"
+	);
+
+	// continue can't jump out of a function, so these are left for the compiler to reject
+	assert_eq!(to_lua("continue"), "continue");
+	assert_eq!(
+		to_lua("while a do f(function() continue end) end"),
+		"while a do f(function() continue end) end"
+	);
+}
+
+#[test]
+fn glua_compilation() {
+	let compiler = super::builder().glua(true).build().unwrap();
+	assert!(compiler.is_glua_preprocessed());
+
+	assert_compiles_to(&compiler, "if a != b && !c then return end", "if a ~= b and not c then return end");
+	assert_compiles_to(
+		&compiler,
+		"for i = 1, 10 do\n\tlocal x = i * 2\n\tif x % 3 == 0 then continue end\n\tprint(x)\nend",
+		"for i = 1, 10 do do\n\tlocal x = i * 2\n\tif x % 3 == 0 then goto skip end\n\tprint(x)\nend ::skip:: end",
+	);
+
+	// The loop body can end with a return or break, which must be the last statement of a block
+	assert_compiles_to(
+		&compiler,
+		"while true do if a then continue end break end",
+		"while true do do if a then goto skip end break end ::skip:: end",
+	);
+
+	// Line numbers are kept, so syntax errors point at the same line
+	let error = compiler.compile_named("/* a\nb\n*/ x = // c\n!= 1", "=glua", false).unwrap_err();
+	assert_eq!(error.diagnostic().unwrap().line, 4);
+
+	let error = compiler.compile_str("continue", false).unwrap_err();
+	assert!(matches!(error, crate::LuaError::SyntaxError(_)));
+
+	// Files are preprocessed too, keeping luaL_loadfile's chunk name and errors
+	let path = std::env::temp_dir().join(format!("gluac-rs-glua-{}.lua", std::process::id()));
+	std::fs::write(&path, "// comment\nreturn 1 != 2").unwrap();
+	let bytecode = compiler.compile_path(&path, false);
+	std::fs::remove_file(&path).unwrap();
+	let dump = crate::bytecode::parse(&bytecode.unwrap()).unwrap();
+	assert_eq!(dump.header.chunk_name, Some(format!("@{}", path.display()).into_bytes()));
+
+	let error = compiler.compile_path("this file does not exist", false).unwrap_err();
+	assert!(matches!(error, crate::LuaError::FileError(_)));
+	assert_eq!(error.to_string(), "cannot open this file does not exist: No such file or directory");
+	let native = super::builder().glua(false).build().unwrap();
+	assert_eq!(
+		native.compile_path("this file does not exist", false).unwrap_err().to_string(),
+		error.to_string()
+	);
+
+	assert!(!super::builder().glua(false).build().unwrap().is_glua_preprocessed());
+}
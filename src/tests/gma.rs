@@ -0,0 +1,95 @@
+use crate::gma::{Gma, GmaError, GmaFile};
+
+fn gma() -> Gma {
+	let mut gma = Gma::new("Test Addon");
+	gma.timestamp = 0x0102030405060708;
+	gma.addon_type = "tool".to_string();
+	gma.tags = vec!["fun".to_string(), "roleplay".to_string()];
+	gma.description = "A \"test\" addon".to_string();
+	gma.files.push(GmaFile::new("lua/autorun/init.lua", &b"print('Hello, world!')"[..]));
+	gma.files.push(GmaFile::new("materials/empty.vmt", Vec::new()));
+	gma
+}
+
+#[test]
+fn gma_round_trip() {
+	let gma = gma();
+	let data = gma.write().unwrap();
+	assert_eq!(Gma::read(&data).unwrap(), gma);
+	assert_eq!(gma.file("materials/empty.vmt").unwrap().data, b"");
+
+	// Compiled files can be packed too
+	let bytecode = super::compiler().unwrap().compile_str("print('Hello, world!')", true).unwrap();
+	let mut packed = Gma::new("Compiled");
+	packed.files.push(GmaFile::new("lua/autorun/init.lua", bytecode.clone()));
+	let packed = Gma::read(&packed.write().unwrap()).unwrap();
+	assert_eq!(packed.files[0].data, bytecode);
+	assert_eq!(packed.author, "Author Name");
+	assert_eq!(packed.version, 1);
+}
+
+#[test]
+fn gma_layout() {
+	let mut gma = Gma::new("A");
+	gma.timestamp = 0x0102030405060708;
+	gma.files.push(GmaFile::new("a.txt", &b"hi"[..]));
+	let data = gma.write().unwrap();
+
+	let mut expected = Vec::new();
+	expected.extend_from_slice(b"GMAD\x03");
+	expected.extend_from_slice(&0u64.to_le_bytes()); // SteamID
+	expected.extend_from_slice(&[8, 7, 6, 5, 4, 3, 2, 1]); // Timestamp
+	expected.push(0); // No required content
+	expected.extend_from_slice(b"A\0{\"description\":\"\",\"tags\":[],\"type\":\"\"}\0Author Name\0");
+	expected.extend_from_slice(&1i32.to_le_bytes());
+	expected.extend_from_slice(&1u32.to_le_bytes());
+	expected.extend_from_slice(b"a.txt\0");
+	expected.extend_from_slice(&2u64.to_le_bytes());
+	expected.extend_from_slice(&0xD8932AACu32.to_le_bytes()); // CRC32 of "hi"
+	expected.extend_from_slice(&0u32.to_le_bytes());
+	expected.extend_from_slice(b"hi");
+	let crc = crc32fast::hash(&expected);
+	expected.extend_from_slice(&crc.to_le_bytes());
+	assert_eq!(data, expected);
+}
+
+#[test]
+fn gma_description() {
+	// Version 1 archives have no required content, and other tools may not store the description as JSON
+	let mut data = Vec::new();
+	data.extend_from_slice(b"GMAD\x01");
+	data.extend_from_slice(&[0; 16]);
+	data.extend_from_slice(b"Old Addon\0Just a description\0Someone\0");
+	data.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+
+	let gma = Gma::read(&data).unwrap();
+	assert_eq!(gma.name, "Old Addon");
+	assert_eq!(gma.description, "Just a description");
+	assert_eq!(gma.addon_type, "");
+	assert_eq!(gma.author, "Someone");
+	assert!(gma.files.is_empty());
+}
+
+#[test]
+fn gma_errors() {
+	let data = gma().write().unwrap();
+
+	assert_eq!(Gma::read(b"LJ\x1B\x02"), Err(GmaError::InvalidIdent));
+	assert_eq!(Gma::read(b"GMAD\x04"), Err(GmaError::UnsupportedVersion(4)));
+	assert_eq!(Gma::read(&data[..data.len() - 10]), Err(GmaError::UnexpectedEof));
+
+	let mut corrupted = data.clone();
+	let offset = data.windows(5).position(|window| window == b"print").unwrap();
+	corrupted[offset] = b'P';
+	assert_eq!(
+		Gma::read(&corrupted),
+		Err(GmaError::CrcMismatch {
+			path: "lua/autorun/init.lua".to_string(),
+			offset
+		})
+	);
+
+	let mut gma = gma();
+	gma.files[0].path.push('\0');
+	assert!(matches!(gma.write(), Err(GmaError::InvalidString(_))));
+}
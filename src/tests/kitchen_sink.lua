@@ -0,0 +1,61 @@
+local config = {
+	"first", "second", 3, 4.5, true, false,
+	name = "kitchen sink",
+	count = 42,
+	ratio = 0.25,
+	enabled = true,
+	[10] = "ten",
+}
+
+local big = 2147483647
+local negative = -1234567
+local fraction = -0.5
+local huge = 1e300
+
+local function sum(...)
+	local total = 0
+	for i = 1, select("#", ...) do
+		total = total + select(i, ...)
+	end
+	return total
+end
+
+local counter = 0
+local function increment(by)
+	counter = counter + (by or 1)
+	return function()
+		return counter, big, negative
+	end
+end
+
+local Object = {}
+Object.__index = Object
+
+function Object.new(name)
+	return setmetatable({ name = name }, Object)
+end
+
+function Object:greet(greeting)
+	return greeting .. ", " .. self.name .. "!"
+end
+
+for key, value in pairs(config) do
+	if type(value) == "number" and value ~= 3 then
+		counter = counter + value
+	elseif value == nil then
+		break
+	end
+end
+
+local i = 0
+while i < 10 do
+	i = i + 1
+	repeat
+		i = i * 2
+	until i > 5
+end
+
+local object = Object.new("world")
+print(object:greet("Hello"), sum(1, 2, 3), increment(2)(), fraction, huge, #config, config.name)
+
+return config, sum, increment
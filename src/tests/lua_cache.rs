@@ -0,0 +1,48 @@
+use crate::lua_cache::{LuaCacheEntry, LuaCacheError, HASH_LEN};
+
+/// `print('Hello, world!')` and a NUL byte, compressed by another LZMA implementation without the size in the header.
+const HELLO_WORLD_LZMA: &str = "5d00008000ffffffffffffffff00381c89568c0b997142a731148b87eb2847bc0a053458f40de8816dbffff82bc000";
+const HELLO_WORLD_NUL_SHA1: &str = "f74574bdc15fd60a98a6f67ff021d5ed1fe03b4c";
+
+fn unhex(hex: &str) -> Vec<u8> {
+	(0..hex.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+		.collect()
+}
+
+#[test]
+fn lua_cache_read() {
+	let mut data = unhex(HELLO_WORLD_NUL_SHA1);
+	data.extend(unhex(HELLO_WORLD_LZMA));
+
+	let entry = LuaCacheEntry::read(&data).unwrap();
+	assert_eq!(entry.contents, b"print('Hello, world!')\0");
+	assert_eq!(entry.file_name(), format!("{}.lua", HELLO_WORLD_NUL_SHA1));
+	assert!(!entry.is_bytecode());
+
+	assert_eq!(LuaCacheEntry::read(&data[..10]), Err(LuaCacheError::TooShort));
+	assert!(matches!(LuaCacheEntry::read(&data[..30]), Err(LuaCacheError::Decompress(_))));
+
+	data[0] ^= 1;
+	assert!(matches!(LuaCacheEntry::read(&data), Err(LuaCacheError::HashMismatch { .. })));
+}
+
+#[test]
+fn lua_cache_write() {
+	let entry = LuaCacheEntry::new(&b"print('Hello, world!')"[..]);
+	assert_eq!(entry.file_name(), "052e367c8ad4e2b7f258c73b99b23a021d1705f5.lua");
+
+	let data = entry.write();
+	assert_eq!(&data[..HASH_LEN], &unhex("052e367c8ad4e2b7f258c73b99b23a021d1705f5")[..]);
+	// The decompressed size is in the header, as util.Compress writes it
+	assert_eq!(&data[HASH_LEN + 5..HASH_LEN + 13], &22u64.to_le_bytes());
+	assert_eq!(LuaCacheEntry::read(&data).unwrap(), entry);
+
+	let bytecode = super::compiler().unwrap().compile_str("print('Hello, world!')", true).unwrap();
+	let entry = LuaCacheEntry::read(&LuaCacheEntry::new(bytecode.clone()).write()).unwrap();
+	assert!(entry.is_bytecode());
+	assert_eq!(entry.contents, bytecode);
+
+	assert_eq!(LuaCacheEntry::read(&LuaCacheEntry::new(Vec::new()).write()).unwrap().contents, b"");
+}
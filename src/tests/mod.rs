@@ -1,3 +1,30 @@
-// mod.rs was getting confusing :[
-mod tests;
-pub use tests::*;
+/// The builder the tests compile with, which uses the LuaJIT linked in by the `luajit` feature if it's enabled so that they can run without `lua_shared`.
+fn builder() -> crate::CompilerBuilder {
+	#[cfg(feature = "luajit")]
+	return crate::CompilerBuilder::new().backend(crate::backend::LinkedLuaJit);
+
+	#[cfg(not(feature = "luajit"))]
+	crate::CompilerBuilder::new()
+}
+
+fn compiler() -> Result<crate::BytecodeCompiler, crate::LuaError> {
+	builder().build()
+}
+
+// mod.rs was getting confusing :[
+#[allow(clippy::module_inception, clippy::explicit_auto_deref)]
+mod tests;
+
+mod bytecode;
+
+mod cache;
+
+mod diagnostic;
+
+mod glua;
+
+mod gma;
+
+mod lua_cache;
+
+mod realm;
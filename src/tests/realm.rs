@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use crate::realm::{Ambiguity, Realm};
+
+fn classify(path: &str) -> Result<Realm, Ambiguity> {
+	Realm::classify(Path::new(path))
+}
+
+#[test]
+fn realm_classify() {
+	// autorun directories
+	assert_eq!(classify("autorun/init.lua"), Ok(Realm::Shared));
+	assert_eq!(classify("autorun/server/init.lua"), Ok(Realm::Server));
+	assert_eq!(classify("autorun/client/hud.lua"), Ok(Realm::Client));
+	assert_eq!(classify("addons/myaddon/lua/autorun/server/sv_init.lua"), Ok(Realm::Server));
+	assert_eq!(classify("lua/Autorun/Client/Hud.lua"), Ok(Realm::Client));
+
+	// Prefixes
+	assert_eq!(classify("mymodule/sv_database.lua"), Ok(Realm::Server));
+	assert_eq!(classify("mymodule/cl_menu.lua"), Ok(Realm::Client));
+	assert_eq!(classify("mymodule/sh_config.lua"), Ok(Realm::Shared));
+	assert_eq!(classify("autorun/sh_init.lua"), Ok(Realm::Shared));
+
+	// Gamemodes and scripted entities
+	assert_eq!(classify("gamemodes/mygamemode/gamemode/init.lua"), Ok(Realm::Server));
+	assert_eq!(classify("gamemodes/mygamemode/gamemode/cl_init.lua"), Ok(Realm::Client));
+	assert_eq!(classify("entities/my_entity/shared.lua"), Ok(Realm::Shared));
+	assert_eq!(classify("lua/weapons/my_weapon/init.lua"), Ok(Realm::Server));
+	assert_eq!(classify("effects/my_effect/shared.lua"), Ok(Realm::Shared));
+
+	// Ambiguous files
+	assert_eq!(classify("mymodule/util.lua"), Err(Ambiguity::Unknown));
+	assert_eq!(classify("autorun/other/util.lua"), Err(Ambiguity::Unknown));
+	assert_eq!(classify("server.lua"), Err(Ambiguity::Unknown));
+	assert_eq!(classify("mylib/init.lua"), Err(Ambiguity::Unknown));
+	assert_eq!(classify("init.lua"), Err(Ambiguity::Unknown));
+	assert_eq!(classify("mymodule/shared.lua"), Err(Ambiguity::Unknown));
+	assert_eq!(classify("gamemodes/mygamemode/init.lua"), Err(Ambiguity::Unknown));
+	assert_eq!(classify("entities/my_entity/modules/init.lua"), Err(Ambiguity::Unknown));
+	assert_eq!(
+		classify("autorun/server/cl_menu.lua"),
+		Err(Ambiguity::Conflict {
+			folder: Realm::Server,
+			name: Realm::Client
+		})
+	);
+	assert_eq!(
+		classify("autorun/cl_menu.lua"),
+		Err(Ambiguity::Conflict {
+			folder: Realm::Shared,
+			name: Realm::Client
+		})
+	);
+
+	assert!(Realm::Shared.is_server() && Realm::Shared.is_client());
+	assert!(Realm::Server.is_server() && !Realm::Server.is_client());
+	assert!(!Realm::Client.is_server() && Realm::Client.is_client());
+}
@@ -1,21 +1,21 @@
+pub(super) const HELLO_WORLD_X86_64: &[u8] = &[
+	27, 76, 74, 2, 10, 43, 2, 0, 3, 0, 2, 0, 4, 54, 0, 0, 0, 39, 2, 1, 0, 66, 0, 2, 1, 75, 0, 1, 0, 18, 72, 101, 108, 108, 111, 44, 32, 119, 111,
+	114, 108, 100, 33, 10, 112, 114, 105, 110, 116, 0,
+];
+
+pub(super) const HELLO_WORLD_X86: &[u8] = &[
+	27, 76, 74, 1, 2, 43, 2, 0, 2, 0, 2, 0, 4, 52, 0, 0, 0, 37, 1, 1, 0, 62, 0, 2, 1, 71, 0, 1, 0, 18, 72, 101, 108, 108, 111, 44, 32, 119, 111, 114,
+	108, 100, 33, 10, 112, 114, 105, 110, 116, 0,
+];
+
 fn verify_hello_world_bytecode(bytecode: &[u8]) {
+	super::bytecode::assert_round_trip(bytecode);
+
 	#[cfg(target_pointer_width = "64")]
-	assert_eq!(
-		bytecode,
-		[
-			27, 76, 74, 2, 10, 43, 2, 0, 3, 0, 2, 0, 4, 54, 0, 0, 0, 39, 2, 1, 0, 66, 0, 2, 1, 75, 0, 1, 0, 18, 72, 101, 108, 108, 111, 44, 32, 119,
-			111, 114, 108, 100, 33, 10, 112, 114, 105, 110, 116, 0
-		]
-	);
+	assert_eq!(bytecode, HELLO_WORLD_X86_64);
 
 	#[cfg(target_pointer_width = "32")]
-	assert_eq!(
-		bytecode,
-		[
-			27, 76, 74, 1, 2, 43, 2, 0, 2, 0, 2, 0, 4, 52, 0, 0, 0, 37, 1, 1, 0, 62, 0, 2, 1, 71, 0, 1, 0, 18, 72, 101, 108, 108, 111, 44, 32, 119,
-			111, 114, 108, 100, 33, 10, 112, 114, 105, 110, 116, 0
-		]
-	);
+	assert_eq!(bytecode, HELLO_WORLD_X86);
 }
 
 fn check_stack(compiler: crate::compiler::BytecodeCompiler) {
@@ -65,7 +65,7 @@ fn compile_hello_world_file(compiler: &crate::compiler::BytecodeCompiler) {
 
 #[test]
 fn hello_world_file() {
-	let compiler = crate::compiler().unwrap();
+	let compiler = super::compiler().unwrap();
 
 	compile_hello_world_file(&compiler);
 
@@ -74,7 +74,7 @@ fn hello_world_file() {
 
 #[test]
 fn multiple_hello_world_file() {
-	let compiler = crate::compiler().unwrap();
+	let compiler = super::compiler().unwrap();
 
 	for _ in 1..10 {
 		compile_hello_world_file(&compiler);
@@ -85,7 +85,7 @@ fn multiple_hello_world_file() {
 
 #[test]
 fn concurrent_hello_world_file() {
-	let compiler = std::sync::Arc::new(crate::compiler().unwrap());
+	let compiler = std::sync::Arc::new(super::compiler().unwrap());
 
 	let mut handles = vec![];
 	for _ in 1..10 {
@@ -102,7 +102,7 @@ fn concurrent_hello_world_file() {
 
 #[test]
 fn invalid_file() {
-	let compiler = crate::compiler().unwrap();
+	let compiler = super::compiler().unwrap();
 
 	compile_invalid_file(&compiler);
 
@@ -111,7 +111,7 @@ fn invalid_file() {
 
 #[test]
 fn multiple_invalid_file() {
-	let compiler = crate::compiler().unwrap();
+	let compiler = super::compiler().unwrap();
 
 	for _ in 1..10 {
 		compile_invalid_file(&compiler);
@@ -122,7 +122,7 @@ fn multiple_invalid_file() {
 
 #[test]
 fn concurrent_invalid_file() {
-	let compiler = std::sync::Arc::new(crate::compiler().unwrap());
+	let compiler = std::sync::Arc::new(super::compiler().unwrap());
 
 	let mut handles = vec![];
 	for _ in 1..10 {
@@ -139,7 +139,7 @@ fn concurrent_invalid_file() {
 
 #[test]
 fn hello_world() {
-	let compiler = crate::compiler().unwrap();
+	let compiler = super::compiler().unwrap();
 
 	compile_hello_world_string(&compiler);
 
@@ -148,7 +148,7 @@ fn hello_world() {
 
 #[test]
 fn syntax_error() {
-	let compiler = crate::compiler().unwrap();
+	let compiler = super::compiler().unwrap();
 
 	compile_syntax_error(&compiler);
 
@@ -157,7 +157,7 @@ fn syntax_error() {
 
 #[test]
 fn multiple_compilations() {
-	let compiler = crate::compiler().unwrap();
+	let compiler = super::compiler().unwrap();
 
 	for _ in 1..10 {
 		compile_hello_world_string(&compiler);
@@ -168,7 +168,7 @@ fn multiple_compilations() {
 
 #[test]
 fn concurrent_compilations() {
-	let compiler = std::sync::Arc::new(crate::compiler().unwrap());
+	let compiler = std::sync::Arc::new(super::compiler().unwrap());
 
 	let mut handles = vec![];
 	for _ in 1..10 {
@@ -185,7 +185,7 @@ fn concurrent_compilations() {
 
 #[test]
 fn multiple_syntax_error_compilations() {
-	let compiler = crate::compiler().unwrap();
+	let compiler = super::compiler().unwrap();
 
 	for _ in 1..10 {
 		compile_syntax_error(&compiler);
@@ -196,7 +196,7 @@ fn multiple_syntax_error_compilations() {
 
 #[test]
 fn concurrent_syntax_error_compilations() {
-	let compiler = std::sync::Arc::new(crate::compiler().unwrap());
+	let compiler = std::sync::Arc::new(super::compiler().unwrap());
 
 	let mut handles = vec![];
 	for _ in 1..10 {
@@ -213,7 +213,7 @@ fn concurrent_syntax_error_compilations() {
 
 #[test]
 fn multiple_mixed_compilations() {
-	let compiler = crate::compiler().unwrap();
+	let compiler = super::compiler().unwrap();
 
 	for i in 1..10 {
 		if i % 2 == 0 {
@@ -228,7 +228,7 @@ fn multiple_mixed_compilations() {
 
 #[test]
 fn concurrent_mixed_compilations() {
-	let compiler = std::sync::Arc::new(crate::compiler().unwrap());
+	let compiler = std::sync::Arc::new(super::compiler().unwrap());
 
 	let mut handles = vec![];
 	for i in 1..10 {
@@ -248,3 +248,337 @@ fn concurrent_mixed_compilations() {
 
 	check_stack(std::sync::Arc::try_unwrap(compiler).unwrap());
 }
+
+#[test]
+fn safe_compilation() {
+	let compiler = super::compiler().unwrap();
+
+	verify_hello_world_bytecode(&compiler.compile_str(r#"print("Hello, world!")"#, true).unwrap());
+	verify_hello_world_bytecode(&compiler.compile_bytes(br#"print("Hello, world!")"#, true).unwrap());
+	verify_hello_world_bytecode(
+		&compiler
+			.compile_path(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/tests/hello_world.lua"), true)
+			.unwrap(),
+	);
+
+	// The chunk name should be the same as with luaL_loadstring
+	assert_eq!(
+		compiler.compile_str(r#"print("Hello, world!")"#, false).unwrap(),
+		compiler.compile_string(lua_string!(r#"print("Hello, world!")"#), false).unwrap()
+	);
+
+	let bytecode = compiler.compile_bytes(b"return \"\0binary\xFF\"", true).unwrap();
+	let dump = crate::bytecode::parse(&bytecode).unwrap();
+	assert_eq!(dump.main.gc_constants, [crate::bytecode::GcConstant::String(b"\0binary\xFF".to_vec())]);
+
+	assert!(matches!(
+		compiler.compile_bytes(b"\0 invalid", true),
+		Err(crate::LuaError::SyntaxError(_))
+	));
+	assert!(matches!(
+		compiler.compile_path("this file does not exist", true),
+		Err(crate::LuaError::FileError(_))
+	));
+	assert!(matches!(compiler.compile_path("nul\0path", true), Err(crate::LuaError::NulError(_))));
+
+	check_stack(compiler);
+}
+
+#[test]
+fn chunk_names() {
+	let compiler = super::compiler().unwrap();
+
+	let bytecode = compiler
+		.compile_named(r#"print("Hello, world!")"#, "@addons/myaddon/lua/autorun/init.lua", false)
+		.unwrap();
+	let dump = crate::bytecode::parse(&bytecode).unwrap();
+	assert_eq!(dump.header.chunk_name.as_deref(), Some(&b"@addons/myaddon/lua/autorun/init.lua"[..]));
+	verify_hello_world_bytecode(&crate::bytecode::strip(&bytecode).unwrap());
+
+	match compiler.compile_named("Invalid Lua code", "@addons/myaddon/lua/autorun/init.lua", true) {
+		Err(crate::LuaError::SyntaxError(Some(message))) => assert!(message.starts_with("addons/myaddon/lua/autorun/init.lua:1:"), "{}", message),
+		result => panic!("expected a syntax error, got {:?}", result),
+	}
+
+	match compiler.compile_named("Invalid Lua code", "=stdin", true) {
+		Err(crate::LuaError::SyntaxError(Some(message))) => assert!(message.starts_with("stdin:1:"), "{}", message),
+		result => panic!("expected a syntax error, got {:?}", result),
+	}
+
+	assert!(matches!(
+		compiler.compile_named("return", "@nul\0name", true),
+		Err(crate::LuaError::NulError(_))
+	));
+
+	check_stack(compiler);
+}
+
+#[test]
+fn syntax_checking() {
+	let compiler = super::compiler().unwrap();
+
+	compiler.check_str(r#"print("Hello, world!")"#).unwrap();
+	compiler.check_bytes(b"return \"\0binary\xFF\"").unwrap();
+	compiler.check_string(lua_string!(r#"print("Hello, world!")"#)).unwrap();
+	compiler
+		.check_path(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/tests/hello_world.lua"))
+		.unwrap();
+
+	// Runtime errors aren't syntax errors, since nothing is run
+	compiler.check_str("error('not run')").unwrap();
+
+	match compiler.check_named("Invalid Lua code", "@addons/myaddon/lua/autorun/init.lua") {
+		Err(error) => assert_eq!(
+			error.diagnostic().unwrap().to_string(),
+			"addons/myaddon/lua/autorun/init.lua:1: '=' expected near 'Lua'"
+		),
+		result => panic!("expected a syntax error, got {:?}", result),
+	}
+	assert!(matches!(compiler.check_str("Invalid Lua code"), Err(crate::LuaError::SyntaxError(_))));
+	assert!(matches!(
+		compiler.check_path("this file does not exist"),
+		Err(crate::LuaError::FileError(_))
+	));
+	assert!(matches!(
+		compiler.check_file(lua_string!("this file does not exist")),
+		Err(crate::LuaError::FileError(_))
+	));
+
+	// Checking leaves the stack as it was, so compiling afterwards still works
+	verify_hello_world_bytecode(&compiler.compile_str(r#"print("Hello, world!")"#, true).unwrap());
+
+	let pool = super::builder().pool_size(2).build_pool().unwrap();
+	pool.check_str(r#"print("Hello, world!")"#).unwrap();
+	assert!(pool.check_named("Invalid Lua code", "=stdin").is_err());
+
+	check_stack(compiler);
+}
+
+#[test]
+fn lua_error_display() {
+	use crate::LuaError;
+	use std::error::Error;
+
+	fn compile(src: &str) -> Result<crate::Bytecode, Box<dyn Error>> {
+		Ok(super::compiler()?.compile_str(src, true)?)
+	}
+	assert_eq!(
+		compile("Invalid Lua code").unwrap_err().to_string(),
+		r#"[string "Invalid Lua code"]:1: '=' expected near 'Lua'"#
+	);
+
+	let nul_error = LuaError::from(std::ffi::CString::new("a\0b").unwrap_err());
+	assert_eq!(nul_error.to_string(), "string contains a NUL byte at position 1");
+	assert!(nul_error.source().is_some());
+
+	let io_error = LuaError::from(std::io::Error::new(std::io::ErrorKind::NotFound, "not found"));
+	assert_eq!(io_error.to_string(), "not found");
+	assert!(io_error.source().is_some());
+
+	// Building a compiler could still find lua_shared through the dynamic linker, so make the error ourselves
+	let load_error = LuaError::LoadError(crate::library::not_found(vec![crate::LoadAttempt {
+		path: "this library does not exist".into(),
+		error: "not found".to_string(),
+	}]));
+	assert!(load_error.to_string().contains("Tried this library does not exist: not found"));
+	assert!(load_error.source().is_some());
+
+	assert_eq!(LuaError::SyntaxError(None).to_string(), "syntax error");
+	assert_eq!(LuaError::Unknown(42).to_string(), "unknown Lua error code 42");
+}
+
+#[test]
+fn backends() {
+	use crate::backend::LuaJit;
+
+	// A stock LuaJIT compiles the same bytecode as lua_shared if it's the same version, so use it if it's installed
+	match crate::CompilerBuilder::new()
+		.backend(LuaJit::new().library_path("this library does not exist"))
+		.build()
+	{
+		Ok(compiler) => verify_hello_world_bytecode(&compiler.compile_str(r#"print("Hello, world!")"#, true).unwrap()),
+		Err(error) => {
+			assert!(matches!(error, crate::LuaError::LoadError(_)));
+			assert!(error.to_string().starts_with("Failed to load LuaJIT!"));
+			assert!(error.to_string().contains("this library does not exist"));
+		}
+	}
+
+	#[cfg(feature = "luajit")]
+	{
+		let compiler = crate::CompilerBuilder::new().backend(crate::backend::LinkedLuaJit).build().unwrap();
+		assert_eq!(compiler.library_path(), std::env::current_exe().unwrap());
+		verify_hello_world_bytecode(&compiler.compile_str(r#"print("Hello, world!")"#, true).unwrap());
+	}
+}
+
+#[test]
+fn compiler_pool() {
+	let pool = super::builder().pool_size(4).build_pool().unwrap();
+	assert_eq!(pool.size(), 4);
+
+	// Every compiler can be in use at once
+	let compilers = (0..4).map(|_| pool.get()).collect::<Vec<_>>();
+	assert!(pool.try_get().is_none());
+	for compiler in &compilers {
+		assert!(!compiler.is_locked());
+		verify_hello_world_bytecode(&compiler.compile_str(r#"print("Hello, world!")"#, true).unwrap());
+	}
+	drop(compilers);
+	assert!(pool.try_get().is_some());
+
+	assert!(super::builder().build_pool().unwrap().size() >= 1);
+}
+
+#[test]
+fn concurrent_compiler_pool() {
+	let pool = std::sync::Arc::new(super::builder().pool_size(3).build_pool().unwrap());
+
+	let mut handles = vec![];
+	for i in 1..10 {
+		let pool = pool.clone();
+		handles.push(std::thread::spawn(move || {
+			for _ in 0..10 {
+				if i % 2 == 0 {
+					verify_hello_world_bytecode(&pool.compile_str(r#"print("Hello, world!")"#, true).unwrap());
+				} else {
+					assert!(matches!(pool.compile_str("Invalid Lua code", true), Err(crate::LuaError::SyntaxError(_))));
+				}
+			}
+		}));
+	}
+
+	assert!(handles.into_iter().try_for_each(|handle| handle.join()).is_ok());
+
+	let compilers = (0..pool.size()).map(|_| pool.get()).collect::<Vec<_>>();
+	for compiler in compilers {
+		assert_eq!(compiler.stack_size(), 3);
+		assert_eq!(compiler.get_type(compiler.stack_size()), "function");
+	}
+}
+
+#[test]
+fn library_bitness() {
+	let exe = std::env::current_exe().unwrap();
+	assert_eq!(crate::library::library_bitness(&exe), Some(crate::library::TARGET_BITNESS));
+	assert_eq!(crate::library::library_bitness(std::path::Path::new("this file does not exist")), None);
+}
+
+#[test]
+fn library_search_order() {
+	let search = crate::library::LibrarySearch {
+		library_paths: vec!["this file does not exist".into()],
+		gmod_paths: vec![],
+		env: false,
+		auto_probe: false,
+	};
+
+	let candidates = search.candidates();
+	assert_eq!(candidates[0], std::path::Path::new("this file does not exist"));
+	assert!(crate::library::LIBRARY_NAMES
+		.iter()
+		.all(|name| candidates.contains(&std::path::PathBuf::from(name))));
+}
+
+#[test]
+fn steam_library_folders() {
+	let vdf = r#"
+"libraryfolders"
+{
+	"0"
+	{
+		"path"		"/home/user/.local/share/Steam"
+		"label"		""
+		"apps"
+		{
+			"4000"		"4046205952"
+		}
+	}
+	"1"
+	{
+		"path"		"D:\\SteamLibrary"
+	}
+}
+"#;
+	assert_eq!(
+		crate::library::parse_library_folders(vdf),
+		[
+			std::path::PathBuf::from("/home/user/.local/share/Steam"),
+			std::path::PathBuf::from(r"D:\SteamLibrary")
+		]
+	);
+
+	let legacy_vdf = r#"
+"LibraryFolders"
+{
+	"TimeNextStatsReport"		"1234567890"
+	"ContentStatsID"		"-1234567890"
+	"1"		"E:\\Games\\Steam"
+}
+"#;
+	assert_eq!(
+		crate::library::parse_library_folders(legacy_vdf),
+		[std::path::PathBuf::from(r"E:\Games\Steam")]
+	);
+}
+
+#[test]
+fn load_errors() {
+	use crate::{LoadAttempt, LoadError};
+
+	let lua_shared = std::path::PathBuf::from(crate::library::LIBRARY_NAMES[0]);
+	let not_found = crate::library::not_found(vec![LoadAttempt {
+		path: lua_shared.clone(),
+		error: "not found".to_string(),
+	}]);
+	assert!(matches!(not_found, LoadError::LibraryNotFound { .. }));
+	assert_eq!(not_found.tried().len(), 1);
+	assert!(not_found.to_string().contains(&format!("Tried {}: not found", lua_shared.display())));
+
+	// As if lua_shared were from the wrong branch of Garry's Mod
+	let wrong_bitness = LoadError::WrongBitness {
+		library: lua_shared.clone(),
+		bitness: 16,
+		tried: Vec::new(),
+	};
+	assert!(wrong_bitness.to_string().starts_with(&format!(
+		"{} is a 16-bit library, but this executable is {}-bit!",
+		lua_shared.display(),
+		crate::library::TARGET_BITNESS
+	)));
+
+	let symbol_not_found = LoadError::SymbolNotFound {
+		library: lua_shared.clone(),
+		symbol: "luaL_newstate".to_string(),
+		error: "undefined symbol".to_string(),
+	};
+	assert!(symbol_not_found.tried().is_empty());
+	assert_eq!(
+		symbol_not_found.to_string(),
+		format!("Failed to find symbol \"luaL_newstate\" in {}: undefined symbol", lua_shared.display())
+	);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn load_skips_non_lua_libraries() {
+	use crate::lua::LuaApi;
+
+	// libc loads fine, but isn't a Lua library
+	let error = LuaApi::load(vec!["libc.so.6".into(), "this library does not exist".into()]).unwrap_err();
+	let tried = error.tried();
+	assert_eq!(tried.len(), 2);
+	assert!(
+		tried[0].error.starts_with("Failed to find symbol \"luaL_newstate\" in libc.so.6"),
+		"{}",
+		tried[0].error
+	);
+
+	// A library that isn't Lua doesn't hide a lua_shared after it, which the tests need to be able to load without the `luajit` feature
+	#[cfg(not(feature = "luajit"))]
+	{
+		let lua_shared = crate::library::LIBRARY_NAMES.iter().map(std::path::PathBuf::from);
+		let candidates = std::iter::once("libc.so.6".into()).chain(lua_shared.clone());
+		assert!(std::ptr::eq(LuaApi::load(candidates).unwrap(), LuaApi::load(lua_shared).unwrap()));
+	}
+}